@@ -5,10 +5,13 @@ use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{self, Layer, layer::SubscriberExt};
 mod probe;
 
-use crate::probe::app::App;
-use crate::probe::config::{Cli, Probes};
+use crate::probe::app::{App, Notice};
+use crate::probe::check;
+use crate::probe::config::{Check, Cli, Command, ConfigWatcher, ProbeConfig, Probes, save_filter};
 use crate::probe::event::{Config, Event, Events};
-use crate::probe::inputs::Inputs;
+use crate::probe::headless::{self, Outcome};
+use crate::probe::inputs::{InputEvent, Inputs};
+use crate::probe::record::Recorder;
 use crate::probe::state::AppState;
 use crate::probe::ui;
 use crossterm::{
@@ -17,9 +20,11 @@ use crossterm::{
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{Terminal, backend::CrosstermBackend};
-use std::fs;
+use std::io::Write;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::{error::Error, io, time::Duration};
+use tokio::sync::mpsc;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -28,37 +33,55 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // get config
     let cli: Cli = argh::from_env();
-    let config = fs::read_to_string(cli.config).expect("Something went wrong reading the file");
-    let probes: Probes = toml::from_str(&config).expect("Couldn't parse config file.");
-    probes.validate();
+    if let Some(Command::Check(args)) = &cli.command {
+        std::process::exit(i32::from(!run_check(&cli, args).await?));
+    }
+    let probes = load_probes(&cli.config);
+    if let Some(Command::Replay(replay)) = &cli.command {
+        if !replay.speed.is_finite() || replay.speed <= 0.0 {
+            return Err("--speed must be a number greater than 0".into());
+        }
+    }
+
+    // setup inputs
+    let mut inputs = match &cli.command {
+        Some(Command::Replay(replay)) => Inputs::from_capture(
+            Path::new(&replay.file),
+            (!replay.max_speed).then_some(replay.speed),
+        )?,
+        Some(Command::Check(_)) | None => Inputs::with_probes(&probes.probes),
+    };
+    if let Some(record) = &cli.record {
+        inputs.record_to(Recorder::create(Path::new(record))?);
+    }
+
+    if cli.headless {
+        let app = App::new("Probe", AppState::from_config(&probes)?);
+        match headless::run(inputs, app, &cli, &mut io::stdout().lock()).await? {
+            Outcome::Matched => return Ok(()),
+            Outcome::NothingMatched => std::process::exit(1),
+            Outcome::Silent => std::process::exit(2),
+        }
+    }
 
     // set up terminal
     enable_raw_mode()?;
     io::stdout().execute(EnterAlternateScreen)?;
     let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
 
-    // setup inputs
-    let mut inputs = Inputs::with_probes(&probes.probes);
-
     // set up events and app
     let mut events = Events::with_config(Config {
         tick_rate: Duration::from_millis(cli.tick_rate),
     });
-    let appstate = AppState::from_probes(&probes.probes);
+    let appstate = AppState::from_config(&probes)?;
     let app = App::new("Probe", appstate);
     let app = Arc::new(Mutex::new(app));
 
     // input loop
-    let tapp = Arc::clone(&app);
-    tokio::spawn(async move {
-        loop {
-            let msg = inputs.next().await.expect("Failed to get next input.");
-            {
-                let mut app = tapp.lock().expect("Failed to lock Mutex");
-                app.process_message_for_stream(&msg.0, &msg.1);
-            }
-        }
-    });
+    let (reloads, reload_rx) = mpsc::unbounded_channel();
+    spawn_input_loop(inputs, Arc::clone(&app), reload_rx);
+    let mut watcher = ConfigWatcher::new(&cli.config);
+
     // event loop
     loop {
         {
@@ -67,42 +90,38 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
 
         match events.next().await {
-            Some(Event::Input(key)) => match key.code {
-                KeyCode::Char(c) => {
-                    let mut app = app.lock().expect("Failed to lock Mutex");
-                    app.on_key(c);
-                }
-                KeyCode::Enter => {
-                    let mut app = app.lock().expect("Failed to lock Mutex");
-                    app.on_key('\n');
-                }
-                KeyCode::Up => {
-                    let mut app = app.lock().expect("Failed to lock Mutex");
-                    app.on_up();
-                }
-                KeyCode::Down => {
-                    let mut app = app.lock().expect("Failed to lock Mutex");
-                    app.on_down();
-                }
-                KeyCode::Left => {
-                    let mut app = app.lock().expect("Failed to lock Mutex");
-                    app.on_left();
-                }
-                KeyCode::Right => {
-                    let mut app = app.lock().expect("Failed to lock Mutex");
-                    app.on_right();
-                }
-                _ => {}
-            },
+            Some(Event::Input(key)) => {
+                let mut app = app.lock().expect("Failed to lock Mutex");
+                on_key(&mut app, key.code);
+            }
             Some(Event::Tick) => {
                 info!("got tick");
                 let mut app = app.lock().expect("Failed to lock Mutex");
                 app.on_tick();
+                if watcher.changed() {
+                    app.reload_requested = true;
+                }
             }
             None => {}
         }
 
-        let app = app.lock().expect("Failed to lock Mutex");
+        let mut app = app.lock().expect("Failed to lock Mutex");
+        if std::mem::take(&mut app.reload_requested) {
+            reload_config(&mut app, &cli.config, &reloads);
+        }
+        if std::mem::take(&mut app.probes_changed) {
+            // only fails if the input loop is gone, and then there's nothing to update
+            let _ = reloads.send(app.state.socket_configs());
+        }
+        if let Some((probe, filter)) = app.pending_save.take() {
+            app.notice = Some(match save_filter(&cli.config, &probe, &filter) {
+                Ok(()) => Notice::Info(format!("saved filter of {probe} to {}", cli.config)),
+                Err(e) => Notice::Error(format!("could not save filter: {e}")),
+            });
+            // the probe already has the new filter, no need to reload it
+            watcher.changed();
+        }
+        ring_alerts(&mut app)?;
         if app.should_quit {
             io::stdout().execute(LeaveAlternateScreen)?;
             disable_raw_mode()?;
@@ -113,6 +132,118 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn on_key(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Char(c) => app.on_key(c),
+        KeyCode::Enter => app.on_key('\n'),
+        KeyCode::Backspace => app.on_backspace(),
+        KeyCode::Esc => app.on_escape(),
+        KeyCode::Tab => app.on_tab(),
+        KeyCode::Up => app.on_up(),
+        KeyCode::Down => app.on_down(),
+        KeyCode::PageUp => app.on_page_up(),
+        KeyCode::PageDown => app.on_page_down(),
+        KeyCode::Left => app.on_left(),
+        KeyCode::Right => app.on_right(),
+        _ => {}
+    }
+}
+
+/// Rings the bell for alerts that fired since the last call and starts their commands.
+fn ring_alerts(app: &mut App) -> io::Result<()> {
+    let fired = app.state.alerts.take_fired();
+    if fired.is_empty() {
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x07")?;
+    stdout.flush()?;
+    for fired in fired {
+        if let Err(e) = fired.run_command() {
+            app.notice = Some(Notice::Error(format!(
+                "could not run command of alert {}: {e}",
+                fired.alert
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Feeds input events to the app, and restarts inputs when the probes are reloaded.
+fn spawn_input_loop(
+    mut inputs: Inputs,
+    app: Arc<Mutex<App<'static>>>,
+    mut reloads: mpsc::UnboundedReceiver<Vec<ProbeConfig>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                event = inputs.next() => event,
+                Some(probes) = reloads.recv() => {
+                    inputs.update(&probes);
+                    continue;
+                }
+            };
+            let Some(event) = event else {
+                break;
+            };
+            let mut app = app.lock().expect("Failed to lock Mutex");
+            match event {
+                InputEvent::Message(msg) => {
+                    app.process_message_for_stream(&msg);
+                }
+                InputEvent::Peers { endpoint, peers } => app.update_peers(&endpoint, &peers),
+                InputEvent::Bound { endpoint, local } => app.update_bound(&endpoint, &local),
+                InputEvent::State { endpoint, state } => app.update_connection(&endpoint, &state),
+            }
+        }
+    });
+}
+
+/// Re-reads the config and hands it to the app and the inputs. A broken config leaves the running
+/// probes alone and shows what's wrong instead.
+fn reload_config(app: &mut App, path: &str, inputs: &mpsc::UnboundedSender<Vec<ProbeConfig>>) {
+    match Probes::load(path) {
+        Ok(probes) => match app.reload(&probes) {
+            Ok(()) => {
+                // only fails if the input loop is gone, and then there's nothing to restart
+                let _ = inputs.send(probes.probes);
+            }
+            Err(error) => app.notice = Some(Notice::Error(format!("config not reloaded: {error}"))),
+        },
+        Err(errors) => {
+            let more = match errors.len() {
+                1 => String::new(),
+                n => format!(" (and {} more)", n - 1),
+            };
+            app.notice = Some(Notice::Error(format!(
+                "config not reloaded: {}{more}",
+                errors[0]
+            )));
+        }
+    }
+}
+
+/// `probe check`: returns whether every probe received traffic.
+async fn run_check(cli: &Cli, args: &Check) -> io::Result<bool> {
+    let probes = load_probes(args.config.as_deref().unwrap_or(&cli.config));
+    check::run(&probes.probes, Duration::from_secs(args.wait)).await
+}
+
+/// Loads the config, or prints every problem with it and exits before the terminal is set up.
+fn load_probes(path: &str) -> Probes {
+    match Probes::load(path) {
+        Ok(probes) => probes,
+        Err(errors) => {
+            eprintln!("Invalid config {path}:");
+            for error in errors {
+                eprintln!("  {error}");
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
 #[cfg(not(feature = "console"))]
 fn initialize_logging() -> Result<()> {
     if std::env::var("RUST_LOG").is_ok() {
@@ -0,0 +1,490 @@
+use crate::probe::config::{AlertConfig, ConfigError};
+use crate::probe::inputs::{MessageText, timestamp};
+use crate::probe::state::Probe;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::process::{self, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How many fired alerts the log keeps.
+const LOG_SIZE: usize = 100;
+/// What `matches` counts over unless the alert sets a `window`.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// What an alert watches for.
+#[derive(Clone, Debug)]
+pub enum Condition {
+    RateAbove(usize),
+    RateBelow(usize),
+    Silence(Duration),
+    Matches { count: usize, window: Duration },
+    Pattern(Regex),
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::RateAbove(rate) => write!(f, "rate above {rate}/s"),
+            Condition::RateBelow(rate) => write!(f, "rate below {rate}/s"),
+            Condition::Silence(silence) => write!(f, "silent for over {}s", silence.as_secs()),
+            Condition::Matches { count, window } => {
+                write!(f, "{count} matches within {}s", window.as_secs())
+            }
+            Condition::Pattern(re) => write!(f, "message matches {re}"),
+        }
+    }
+}
+
+/// A rule and whether it is currently firing.
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub name: String,
+    pub probe: String,
+    pub condition: Condition,
+    command: Option<String>,
+    /// Whether the condition held the last time it was checked. The alert only fires again once
+    /// it stopped holding in between.
+    pub active: bool,
+    /// When the probe matched its most recent messages, newest first. Only used by `Matches`.
+    matched: VecDeque<Instant>,
+    /// Whether a message matched `Pattern` since the last tick.
+    hit: bool,
+}
+
+/// One time an alert fired.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fired {
+    pub alert: String,
+    pub probe: String,
+    pub condition: String,
+    /// What tripped it, e.g. the rate or the message that matched.
+    pub detail: String,
+    pub at: SystemTime,
+    pub command: Option<String>,
+}
+
+/// All alert rules, plus a log of what they fired.
+#[derive(Debug, Default)]
+pub struct Alerts {
+    rules: Vec<Alert>,
+    /// Fired alerts, newest first.
+    pub log: VecDeque<Fired>,
+    /// How many of the newest log entries `take_fired` hasn't returned yet.
+    unseen: usize,
+}
+
+impl Alerts {
+    pub fn new(configs: &[AlertConfig]) -> Result<Alerts, ConfigError> {
+        Ok(Alerts {
+            rules: configs
+                .iter()
+                .map(Alert::try_from)
+                .collect::<Result<_, _>>()?,
+            ..Alerts::default()
+        })
+    }
+
+    /// Replaces the rules. Rules that keep their name and condition stay active, so a reload
+    /// doesn't fire them again. The log is kept. If one of the configs can't be turned into a
+    /// rule, the running rules are left alone.
+    pub fn reload(&mut self, configs: &[AlertConfig]) -> Result<(), ConfigError> {
+        let rules = configs
+            .iter()
+            .map(Alert::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut previous = std::mem::take(&mut self.rules);
+        self.rules = rules
+            .into_iter()
+            .map(|mut alert| {
+                if let Some(i) = previous.iter().position(|old| {
+                    old.name == alert.name
+                        && old.probe == alert.probe
+                        && old.condition.to_string() == alert.condition.to_string()
+                }) {
+                    let old = previous.swap_remove(i);
+                    alert.active = old.active;
+                    alert.matched = old.matched;
+                    alert.hit = old.hit;
+                }
+                alert
+            })
+            .collect();
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Names of the alerts on a probe that are firing right now.
+    pub fn active(&self, probe: &str) -> Vec<&str> {
+        self.rules
+            .iter()
+            .filter(|a| a.active && a.probe == probe)
+            .map(|a| a.name.as_str())
+            .collect()
+    }
+
+    /// Checks the alerts that look at single messages, for a message `probe` matched.
+    pub fn on_message(&mut self, probe: &str, msg: &MessageText, at: Instant) {
+        let mut fired = Vec::new();
+        for alert in self.rules.iter_mut().filter(|a| a.probe == probe) {
+            let holds = match &alert.condition {
+                Condition::Matches { count, window } => {
+                    alert.matched.push_front(at);
+                    alert.matched.truncate(*count);
+                    matches_within(&alert.matched, *count, *window, at)
+                }
+                Condition::Pattern(re) => {
+                    let text = msg.joined();
+                    re.is_match(text).then(|| {
+                        alert.hit = true;
+                        text.replace('\n', " | ")
+                    })
+                }
+                _ => continue,
+            };
+            // a pattern that didn't match doesn't end the alert, only a quiet tick does
+            if holds.is_some() || !matches!(alert.condition, Condition::Pattern(_)) {
+                fired.extend(alert.update(holds));
+            }
+        }
+        self.record(fired);
+    }
+
+    /// Checks every alert against the probes as of this tick. Alerts on probes that don't exist
+    /// anymore never fire.
+    pub fn on_tick(&mut self, probes: &[Probe], now: Instant) {
+        let mut fired = Vec::new();
+        for alert in &mut self.rules {
+            let Some(probe) = probes.iter().find(|p| p.name == alert.probe) else {
+                alert.active = false;
+                continue;
+            };
+            let rate = probe.summary.rate;
+            let holds = match &alert.condition {
+                Condition::RateAbove(max) => (rate > *max).then(|| format!("{rate}/s")),
+                Condition::RateBelow(min) => (rate < *min).then(|| format!("{rate}/s")),
+                Condition::Silence(max) => {
+                    let silence = probe.silence();
+                    (silence > *max).then(|| format!("silent for {}s", silence.as_secs()))
+                }
+                Condition::Matches { count, window } => {
+                    matches_within(&alert.matched, *count, *window, now)
+                }
+                // stays active as long as every tick saw a matching message
+                Condition::Pattern(_) => {
+                    alert.active = std::mem::take(&mut alert.hit);
+                    continue;
+                }
+            };
+            fired.extend(alert.update(holds));
+        }
+        self.record(fired);
+    }
+
+    /// Alerts that fired since the last call, oldest first, for the bell and commands.
+    pub fn take_fired(&mut self) -> Vec<Fired> {
+        let fired = self.log.iter().take(self.unseen).rev().cloned().collect();
+        self.unseen = 0;
+        fired
+    }
+
+    /// Adds fired alerts to the log, dropping the oldest entries once it's full.
+    fn record(&mut self, fired: Vec<Fired>) {
+        self.unseen += fired.len();
+        for fired in fired {
+            self.log.push_front(fired);
+        }
+        self.log.truncate(LOG_SIZE);
+        self.unseen = self.unseen.min(LOG_SIZE);
+    }
+}
+
+impl Alert {
+    /// Records whether the condition holds, with a description of why if it does. Returns what
+    /// fired if it just started holding.
+    fn update(&mut self, holds: Option<String>) -> Option<Fired> {
+        let was_active = std::mem::replace(&mut self.active, holds.is_some());
+        let detail = holds.filter(|_| !was_active)?;
+        Some(Fired {
+            alert: self.name.clone(),
+            probe: self.probe.clone(),
+            condition: self.condition.to_string(),
+            detail,
+            at: SystemTime::now(),
+            command: self.command.clone(),
+        })
+    }
+}
+
+/// Whether the newest `count` of `matched` all fall within `window` of `now`, and how many
+/// messages matched if so.
+fn matches_within(
+    matched: &VecDeque<Instant>,
+    count: usize,
+    window: Duration,
+    now: Instant,
+) -> Option<String> {
+    let in_window = matched
+        .iter()
+        .take_while(|at| now.saturating_duration_since(**at) <= window)
+        .count();
+    (in_window >= count).then(|| format!("{in_window} matches within {}s", window.as_secs()))
+}
+
+impl Fired {
+    /// One line for the alert log and headless output.
+    pub fn line(&self) -> String {
+        format!("{} {}: {}", timestamp(self.at), self.alert, self.detail)
+    }
+
+    /// Starts the alert's command, if it has one, without waiting for it to finish. Its output is
+    /// discarded so it can't mess up the display.
+    pub fn run_command(&self) -> io::Result<()> {
+        let Some(command) = &self.command else {
+            return Ok(());
+        };
+        let at = self.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        let mut child = process::Command::new(shell)
+            .arg(flag)
+            .arg(command)
+            .env("PROBE_ALERT", &self.alert)
+            .env("PROBE_ALERT_PROBE", &self.probe)
+            .env("PROBE_ALERT_CONDITION", &self.condition)
+            .env("PROBE_ALERT_DETAIL", &self.detail)
+            .env("PROBE_ALERT_TIME", at.as_secs().to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // wait for it elsewhere so it doesn't linger as a zombie
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+impl TryFrom<&AlertConfig> for Alert {
+    type Error = ConfigError;
+
+    fn try_from(config: &AlertConfig) -> Result<Self, ConfigError> {
+        let condition = if let Some(rate) = config.rate_above {
+            Condition::RateAbove(rate)
+        } else if let Some(rate) = config.rate_below {
+            Condition::RateBelow(rate)
+        } else if let Some(secs) = config.silence {
+            Condition::Silence(Duration::from_secs(secs))
+        } else if let Some(count) = config.matches {
+            Condition::Matches {
+                count,
+                window: config.window.map_or(DEFAULT_WINDOW, Duration::from_secs),
+            }
+        } else if let Some(pattern) = &config.pattern {
+            let regex = Regex::new(pattern).map_err(|error| ConfigError::InvalidAlertPattern {
+                alert: config.label(),
+                error,
+            })?;
+            Condition::Pattern(regex)
+        } else {
+            return Err(ConfigError::AlertCondition(config.label()));
+        };
+        Ok(Alert {
+            name: config
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{}: {condition}", config.probe)),
+            probe: config.probe.clone(),
+            condition,
+            command: config.command.clone(),
+            active: false,
+            matched: VecDeque::new(),
+            hit: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::config::ProbeConfig;
+    use crate::probe::inputs::Message;
+
+    fn alerts(config: AlertConfig) -> Alerts {
+        Alerts::new(&[AlertConfig {
+            probe: String::from("Units"),
+            ..config
+        }])
+        .expect("valid alerts")
+    }
+
+    fn probe(rate: usize) -> Probe {
+        let mut probe = Probe::try_from(ProbeConfig {
+            name: String::from("Units"),
+            ..Default::default()
+        })
+        .expect("valid probe");
+        probe.summary.rate = rate;
+        probe
+    }
+
+    #[test]
+    fn fires_once_until_the_condition_clears() {
+        let mut alerts = alerts(AlertConfig {
+            rate_above: Some(10),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        alerts.on_tick(&[probe(5)], now);
+        assert!(alerts.take_fired().is_empty());
+
+        alerts.on_tick(&[probe(20)], now);
+        let fired = alerts.take_fired();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].alert, "Units: rate above 10/s");
+        assert_eq!(fired[0].detail, "20/s");
+        assert_eq!(alerts.active("Units"), vec!["Units: rate above 10/s"]);
+
+        alerts.on_tick(&[probe(30)], now);
+        assert!(alerts.take_fired().is_empty());
+
+        alerts.on_tick(&[probe(5)], now);
+        assert!(alerts.active("Units").is_empty());
+        alerts.on_tick(&[probe(30)], now);
+        assert_eq!(alerts.take_fired().len(), 1);
+    }
+
+    #[test]
+    fn silence_and_rate_below() {
+        let mut alerts = Alerts::new(&[
+            AlertConfig {
+                probe: String::from("Units"),
+                silence: Some(0),
+                ..Default::default()
+            },
+            AlertConfig {
+                name: Some(String::from("slow")),
+                probe: String::from("Units"),
+                rate_below: Some(1),
+                ..Default::default()
+            },
+        ])
+        .expect("valid alerts");
+        thread::sleep(Duration::from_millis(5));
+        alerts.on_tick(&[probe(0)], Instant::now());
+        let fired: Vec<String> = alerts.take_fired().into_iter().map(|f| f.alert).collect();
+        assert_eq!(fired, vec!["Units: silent for over 0s", "slow"]);
+    }
+
+    #[test]
+    fn match_count_in_a_window() {
+        let mut alerts = alerts(AlertConfig {
+            matches: Some(3),
+            window: Some(10),
+            ..Default::default()
+        });
+        let unit = Message::new("tcp://127.0.0.1:5556", vec![b"UNIT".to_vec()]);
+        let msg = MessageText::new(&unit);
+        let start = Instant::now();
+
+        // three matches, but too far apart
+        for secs in [0, 6, 12] {
+            alerts.on_message("Units", &msg, start + Duration::from_secs(secs));
+        }
+        assert!(alerts.take_fired().is_empty());
+
+        alerts.on_message("Units", &msg, start + Duration::from_secs(13));
+        let fired = alerts.take_fired();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].detail, "3 matches within 10s");
+
+        // the window moves on and the alert clears
+        alerts.on_tick(&[probe(0)], start + Duration::from_secs(20));
+        assert!(alerts.active("Units").is_empty());
+    }
+
+    #[test]
+    fn patterns_fire_once_per_burst() {
+        let mut alerts = alerts(AlertConfig {
+            pattern: Some(String::from("ERROR")),
+            ..Default::default()
+        });
+        let error = Message::new(
+            "tcp://127.0.0.1:5556",
+            vec![b"UNIT".to_vec(), b"ERROR".to_vec()],
+        );
+        let error = MessageText::new(&error);
+        let fine = Message::new(
+            "tcp://127.0.0.1:5556",
+            vec![b"UNIT".to_vec(), b"ok".to_vec()],
+        );
+        let fine = MessageText::new(&fine);
+        let now = Instant::now();
+
+        alerts.on_message("Units", &fine, now);
+        alerts.on_message("Other", &error, now);
+        assert!(alerts.take_fired().is_empty());
+
+        alerts.on_message("Units", &error, now);
+        alerts.on_message("Units", &fine, now);
+        alerts.on_message("Units", &error, now);
+        let fired = alerts.take_fired();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].detail, "UNIT | ERROR");
+
+        // still active through a tick that saw a match, cleared by one that didn't
+        alerts.on_tick(&[probe(0)], now);
+        assert_eq!(alerts.active("Units").len(), 1);
+        alerts.on_tick(&[probe(0)], now);
+        assert!(alerts.active("Units").is_empty());
+        alerts.on_message("Units", &error, now);
+        assert_eq!(alerts.take_fired().len(), 1);
+    }
+
+    #[test]
+    fn reload_keeps_active_alerts_quiet() {
+        let config = AlertConfig {
+            probe: String::from("Units"),
+            rate_above: Some(10),
+            ..Default::default()
+        };
+        let mut alerts = Alerts::new(&[config.clone()]).expect("valid alerts");
+        alerts.on_tick(&[probe(20)], Instant::now());
+        assert_eq!(alerts.take_fired().len(), 1);
+
+        alerts.reload(&[config]).expect("valid alerts");
+        alerts.on_tick(&[probe(20)], Instant::now());
+        assert!(alerts.take_fired().is_empty());
+        assert_eq!(alerts.log.len(), 1);
+    }
+
+    #[test]
+    fn broken_alerts_are_errors() {
+        let no_condition = AlertConfig {
+            probe: String::from("Units"),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Alerts::new(&[no_condition]),
+            Err(ConfigError::AlertCondition(_))
+        ));
+
+        let bad_pattern = AlertConfig {
+            probe: String::from("Units"),
+            pattern: Some(String::from("(")),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Alerts::new(&[bad_pattern]),
+            Err(ConfigError::InvalidAlertPattern { .. })
+        ));
+    }
+}
@@ -1,50 +1,273 @@
-use crate::probe::state::{AppState, Probe, TabsState};
+use crate::probe::config::{ConfigError, ProbeConfig, Probes};
+use crate::probe::inputs::{ConnectionState, Message, MessageText};
+use crate::probe::state::{AppState, ChartState, Probe, TabsState};
+use crate::probe::stats::Zoom;
+use regex::Regex;
+use std::cmp;
+use std::time::Instant;
 
 #[derive(Debug)]
 pub struct App<'a> {
     pub title: &'a str,
     pub should_quit: bool,
+    /// Set by the reload key, cleared by whoever reloads the config.
+    pub reload_requested: bool,
+    /// Set when probes were added or removed, so their inputs need updating. Cleared by whoever
+    /// updates them.
+    pub probes_changed: bool,
+    /// Set when an edited filter should be written back to the config as `(probe, filter)`,
+    /// cleared by whoever writes it.
+    pub pending_save: Option<(String, String)>,
+    pub notice: Option<Notice>,
+    /// The filter being edited, if any. Takes all keys while it's open.
+    pub editor: Option<FilterEditor>,
+    /// The form for a new probe, if open. Takes all keys while it's open.
+    pub form: Option<ProbeForm>,
+    /// Set while the display is frozen.
+    pub paused: Option<Paused>,
+    /// How much time a histogram bar covers.
+    pub zoom: Zoom,
     pub tabs: TabsState,
     pub state: AppState,
 }
 
+/// Input line for a probe's filter, validated as the user types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterEditor {
+    pub probe: String,
+    pub input: String,
+    /// Why `input` isn't a valid regex, if it isn't.
+    pub error: Option<String>,
+}
+
+impl FilterEditor {
+    fn new(probe: &Probe) -> FilterEditor {
+        FilterEditor {
+            probe: probe.name.clone(),
+            input: probe.filter.clone(),
+            error: None,
+        }
+    }
+
+    fn validate(&mut self) {
+        // the last line of a regex syntax error says what's wrong, the others point at where
+        self.error = Regex::new(&self.input).err().map(|e| {
+            let message = e.to_string();
+            let last = message.lines().last().unwrap_or_default();
+            last.trim_start_matches("error: ").to_string()
+        });
+    }
+}
+
+/// Form for adding a probe. Only the settings needed to explore a PUB socket, everything else
+/// uses the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProbeForm {
+    /// Name, address and filter, in the order they're shown.
+    pub fields: [String; 3],
+    /// Index of the field being typed into.
+    pub focus: usize,
+    /// The first problem with the probe as entered, if any.
+    pub error: Option<String>,
+}
+
+impl ProbeForm {
+    pub const LABELS: [&'static str; 3] = ["Name", "Address", "Filter"];
+
+    fn config(&self) -> ProbeConfig {
+        let [name, address, filter] = self.fields.clone();
+        ProbeConfig {
+            name,
+            address,
+            filter: (!filter.is_empty()).then_some(filter),
+            ..Default::default()
+        }
+    }
+
+    fn validate(&mut self, state: &AppState) {
+        let config = self.config();
+        self.error = if config.name.is_empty() {
+            Some(String::from("name is required"))
+        } else {
+            state
+                .check_new_probe(&config)
+                .err()
+                .and_then(|errors| errors.first().map(ToString::to_string))
+        };
+    }
+}
+
+/// What the display shows while paused. The live probes keep receiving in the meantime.
+#[derive(Clone, Debug)]
+pub struct Paused {
+    /// The probes as they were when the display was paused.
+    pub probes: Vec<Probe>,
+    /// Messages matched since.
+    pub new: usize,
+}
+
+/// A one line message for the user, e.g. how a config reload went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Notice {
+    Info(String),
+    Error(String),
+}
+
 impl<'a> App<'a> {
     pub fn new(title: &'a str, state: AppState) -> App<'a> {
         let num_probes = state.probes.len();
         App {
             title,
             should_quit: false,
+            reload_requested: false,
+            pending_save: None,
+            notice: None,
+            editor: None,
+            form: None,
+            probes_changed: false,
+            paused: None,
+            zoom: Zoom::default(),
             tabs: TabsState::new(num_probes),
             state,
         }
     }
 
-    pub fn probes_for_tab(&self) -> Vec<Probe> {
-        self.state
-            .probes_for_tab(self.tabs.selected_tab, self.tabs.probes_per_tab)
+    /// The probes to draw: a frozen copy while paused, the live ones otherwise.
+    pub fn displayed_probes(&self) -> &[Probe] {
+        match &self.paused {
+            Some(paused) => &paused.probes,
+            None => &self.state.probes,
+        }
+    }
+
+    pub fn probes_for_tab(&self) -> &[Probe] {
+        let probes = self.displayed_probes();
+        let (index, num) = (self.tabs.selected_tab, self.tabs.probes_per_tab);
+        let upper = cmp::min(index * num + num, probes.len());
+        &probes[index * num..upper]
+    }
+
+    /// Freezes the display, or goes back to live.
+    pub fn toggle_pause(&mut self) {
+        self.paused = match self.paused {
+            // the live messages moved on, so start at the newest again
+            Some(_) => {
+                self.state.detail.selected = 0;
+                None
+            }
+            None => Some(Paused {
+                probes: self.state.probes.clone(),
+                new: 0,
+            }),
+        };
+    }
+
+    /// Takes a new snapshot after the probes themselves changed, so the frozen display still
+    /// lines up with them. The snapshot shows everything received so far, so nothing is new.
+    fn refreeze(&mut self) {
+        if let Some(paused) = &mut self.paused {
+            paused.probes.clone_from(&self.state.probes);
+            paused.new = 0;
+        }
     }
 
     pub fn on_up(&mut self) {
+        if let Some(form) = &mut self.form {
+            form.focus = form.focus.saturating_sub(1);
+            return;
+        }
+        if self.state.detail_view {
+            self.scroll_detail(-1);
+            return;
+        }
         self.tabs.prev_probe();
     }
 
     pub fn on_down(&mut self) {
+        if let Some(form) = &mut self.form {
+            form.focus = (form.focus + 1).min(ProbeForm::LABELS.len() - 1);
+            return;
+        }
+        if self.state.detail_view {
+            self.scroll_detail(1);
+            return;
+        }
         self.tabs.next_probe();
     }
 
+    pub fn on_page_up(&mut self) {
+        if self.state.detail_view {
+            self.scroll_detail(-page(self.state.detail.page_size));
+        }
+    }
+
+    pub fn on_page_down(&mut self) {
+        if self.state.detail_view {
+            self.scroll_detail(page(self.state.detail.page_size));
+        }
+    }
+
+    fn scroll_detail(&mut self, by: isize) {
+        let len = self.displayed_probes()[self.tabs.selected_probe_index()]
+            .messages()
+            .len();
+        self.state.detail.scroll(by, len);
+    }
+
     pub fn on_right(&mut self) {
-        self.tabs.next();
+        if self.state.chart_view {
+            let retained = self.selected_timeline_len();
+            let step = self.state.chart.step();
+            self.state.chart.pan(-step, retained);
+        } else {
+            self.tabs.next();
+        }
     }
 
     pub fn on_left(&mut self) {
-        self.tabs.previous();
+        if self.state.chart_view {
+            let retained = self.selected_timeline_len();
+            let step = self.state.chart.step();
+            self.state.chart.pan(step, retained);
+        } else {
+            self.tabs.previous();
+        }
     }
 
-    pub fn selected_probe(&self) -> Probe {
-        self.state.probes[self.tabs.selected_probe_index()].clone()
+    /// Seconds of timeline the displayed selected probe has.
+    fn selected_timeline_len(&self) -> usize {
+        self.selected_probe().timeline().len()
+    }
+
+    pub fn selected_probe(&self) -> &Probe {
+        &self.displayed_probes()[self.tabs.selected_probe_index()]
     }
 
     pub fn on_key(&mut self, c: char) {
+        if let Some(editor) = &mut self.editor {
+            if c == '\n' {
+                self.apply_filter(false);
+            } else {
+                editor.input.push(c);
+                editor.validate();
+            }
+            return;
+        }
+        if let Some(form) = &mut self.form {
+            if c == '\n' {
+                self.add_probe();
+            } else {
+                form.fields[form.focus].push(c);
+                form.validate(&self.state);
+            }
+            return;
+        }
+        if self.state.detail_view && self.on_detail_key(c) {
+            return;
+        }
+        if self.state.chart_view && self.on_chart_key(c) {
+            return;
+        }
         match c {
             'q' => {
                 self.should_quit = true;
@@ -61,33 +284,287 @@ impl<'a> App<'a> {
             'l' => {
                 self.on_right();
             }
+            'r' => {
+                self.reload_requested = true;
+            }
+            'p' => {
+                self.toggle_pause();
+            }
+            'e' if !self.state.detail_view => {
+                self.editor = Some(FilterEditor::new(self.selected_probe()));
+            }
+            'a' if !self.state.detail_view => {
+                let mut form = ProbeForm::default();
+                form.validate(&self.state);
+                self.form = Some(form);
+            }
+            'd' if !self.state.detail_view => {
+                self.remove_selected_probe();
+            }
+            'z' if !self.state.detail_view => {
+                self.zoom = self.zoom.next();
+            }
+            'c' if !self.state.detail_view => {
+                self.state.chart_view = true;
+                self.state.chart = ChartState::default();
+            }
             '\n' => {
                 self.state.detail_view = !self.state.detail_view;
+                self.state.detail.selected = 0;
             }
             _ => {}
         }
     }
 
+    /// Keys of the chart view: zooming, going back to the latest data and closing it. Keys for the
+    /// list view that make no sense here are swallowed. Returns whether the key was handled.
+    fn on_chart_key(&mut self, c: char) -> bool {
+        let retained = self.selected_timeline_len();
+        let chart = &mut self.state.chart;
+        match c {
+            '+' | '=' => chart.zoom_in(),
+            '-' => chart.zoom_out(retained),
+            '0' => chart.offset = 0,
+            'c' => self.state.chart_view = false,
+            'e' | 'a' | 'd' | 'z' | '\n' => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Keys only the detail view knows about: search and jumping between matches. Returns whether
+    /// the key was handled.
+    fn on_detail_key(&mut self, c: char) -> bool {
+        let probes = match &self.paused {
+            Some(paused) => &paused.probes,
+            None => &self.state.probes,
+        };
+        let messages = probes[self.tabs.selected_probe_index()].messages();
+        let detail = &mut self.state.detail;
+        let found = match c {
+            '\n' if detail.searching => {
+                detail.searching = false;
+                detail.find(messages, true, true)
+            }
+            c if detail.searching => {
+                detail.query.push(c);
+                return true;
+            }
+            '/' => {
+                detail.searching = true;
+                detail.query.clear();
+                return true;
+            }
+            'n' => detail.find(messages, true, false),
+            'N' => detail.find(messages, false, false),
+            _ => return false,
+        };
+        if !found && !detail.query.is_empty() {
+            self.notice = Some(Notice::Error(format!(
+                "no messages contain {}",
+                detail.query
+            )));
+        }
+        true
+    }
+
+    pub fn on_backspace(&mut self) {
+        if self.state.detail.searching {
+            self.state.detail.query.pop();
+        }
+        if let Some(editor) = &mut self.editor {
+            editor.input.pop();
+            editor.validate();
+        }
+        if let Some(form) = &mut self.form {
+            form.fields[form.focus].pop();
+            form.validate(&self.state);
+        }
+    }
+
+    pub fn on_escape(&mut self) {
+        self.editor = None;
+        self.form = None;
+        self.state.chart_view = false;
+        if self.state.detail.searching {
+            self.state.detail.searching = false;
+            self.state.detail.query.clear();
+        }
+    }
+
+    /// Applies the edited filter and asks for it to be saved to the config, or moves to the next
+    /// field of the probe form.
+    pub fn on_tab(&mut self) {
+        if self.editor.is_some() {
+            self.apply_filter(true);
+        }
+        if let Some(form) = &mut self.form {
+            form.focus = (form.focus + 1) % ProbeForm::LABELS.len();
+        }
+    }
+
+    /// Closes the form and adds its probe, unless it has a problem, in which case the form stays
+    /// open.
+    fn add_probe(&mut self) {
+        let Some(form) = self.form.take_if(|f| f.error.is_none()) else {
+            return;
+        };
+        let config = form.config();
+        let name = config.name.clone();
+        if let Err(error) = self.state.add_probe(config) {
+            self.notice = Some(Notice::Error(error.to_string()));
+            return;
+        }
+        self.notice = Some(Notice::Info(format!("added probe {name}")));
+        self.tabs
+            .recalculate_layout(self.state.probes.len(), self.tabs.probes_per_tab);
+        self.refreeze();
+        self.probes_changed = true;
+    }
+
+    /// Removes the selected probe. The last probe stays, there'd be nothing to show without it.
+    fn remove_selected_probe(&mut self) {
+        if self.state.probes.len() <= 1 {
+            self.notice = Some(Notice::Error(String::from("can't remove the last probe")));
+            return;
+        }
+        let probe = self.state.remove_probe(self.tabs.selected_probe_index());
+        self.notice = Some(Notice::Info(format!("removed probe {}", probe.name)));
+        self.tabs
+            .recalculate_layout(self.state.probes.len(), self.tabs.probes_per_tab);
+        self.refreeze();
+        self.probes_changed = true;
+    }
+
+    /// Closes the editor and sets the filter, unless it's invalid, in which case the editor stays
+    /// open.
+    fn apply_filter(&mut self, save: bool) {
+        let Some(editor) = self.editor.take_if(|e| e.error.is_none()) else {
+            return;
+        };
+        // a reload may have removed the probe in the meantime
+        let Some(probe) = self
+            .state
+            .probes
+            .iter_mut()
+            .find(|p| p.name == editor.probe)
+        else {
+            return;
+        };
+        if probe.set_filter(&editor.input).is_err() {
+            return;
+        }
+        self.refreeze();
+        if save {
+            self.pending_save = Some((editor.probe, editor.input));
+        } else {
+            self.notice = Some(Notice::Info(format!(
+                "filter of {} set to {}",
+                editor.probe, editor.input
+            )));
+        }
+    }
+
+    /// Switches to a new set of probes, keeping the history of probes whose name didn't change.
+    pub fn reload(&mut self, config: &Probes) -> Result<(), ConfigError> {
+        let (added, removed) = self.state.reload(&config.probes)?;
+        self.state.memory_budget = config.memory_budget();
+        self.state.alerts.reload(&config.alerts)?;
+        self.state.enforce_memory_budget();
+        self.tabs
+            .recalculate_layout(self.state.probes.len(), self.tabs.probes_per_tab);
+        self.refreeze();
+        self.notice = Some(Notice::Info(format!(
+            "config reloaded: {added} added, {removed} removed"
+        )));
+        Ok(())
+    }
+
     pub fn on_tick(&mut self) {
         self.state
             .probes
             .iter_mut()
             .for_each(|p: &mut Probe| p.update_state());
+        self.state
+            .alerts
+            .on_tick(&self.state.probes, Instant::now());
+    }
+
+    /// Hands the message to every probe on its endpoint and returns the names of the probes that
+    /// matched it.
+    pub fn process_message_for_stream(&mut self, msg: &Message) -> Vec<String> {
+        let text = MessageText::new(msg);
+        let matched: Vec<usize> = self
+            .state
+            .probes
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, p)| p.address == msg.endpoint && p.is_subscribed(msg))
+            .filter_map(|(i, p)| p.process_message(msg, &text).then_some(i))
+            .collect();
+
+        let now = Instant::now();
+        for &i in &matched {
+            self.state
+                .alerts
+                .on_message(&self.state.probes[i].name, &text, now);
+        }
+        if !matched.is_empty() {
+            self.state.keep(msg, &matched);
+            if let Some(paused) = &mut self.paused {
+                paused.new += 1;
+            }
+        }
+
+        // keep a scrolled detail view on the message it shows, new ones go in at the top
+        let selected = self.tabs.selected_probe_index();
+        if self.paused.is_none()
+            && self.state.detail_view
+            && self.state.detail.selected > 0
+            && matched.contains(&selected)
+        {
+            let len = self.state.probes[selected].messages().len();
+            self.state.detail.scroll(1, len);
+        }
+        matched
+            .into_iter()
+            .map(|i| self.state.probes[i].name.clone())
+            .collect()
     }
 
-    pub fn process_message_for_stream(&mut self, stream: &str, msg: &str) {
+    pub fn update_peers(&mut self, endpoint: &str, peers: &[String]) {
         self.state
             .probes
             .iter_mut()
-            .filter(|p| p.name == stream)
-            .for_each(|p: &mut Probe| p.process_message(msg));
+            .filter(|p| p.address == endpoint)
+            .for_each(|p: &mut Probe| p.peers = peers.to_vec());
     }
+
+    pub fn update_bound(&mut self, endpoint: &str, local: &str) {
+        self.state
+            .probes
+            .iter_mut()
+            .filter(|p| p.address == endpoint)
+            .for_each(|p: &mut Probe| p.bound = Some(local.to_string()));
+    }
+
+    pub fn update_connection(&mut self, endpoint: &str, state: &ConnectionState) {
+        self.state
+            .probes
+            .iter_mut()
+            .filter(|p| p.address == endpoint)
+            .for_each(|p: &mut Probe| p.connection = Some(state.clone()));
+    }
+}
+
+/// A page of messages as a scroll distance, at least one.
+fn page(size: usize) -> isize {
+    isize::try_from(size).unwrap_or(isize::MAX).max(1)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::probe::config::ProbeConfig;
 
     #[test]
     fn single_probe_per_tab_should_stay_selected() {
@@ -96,19 +573,22 @@ mod tests {
                 name: String::from("0"),
                 filter: None,
                 address: String::new(),
+                ..Default::default()
             },
             ProbeConfig {
                 name: String::from("1"),
                 filter: None,
                 address: String::new(),
+                ..Default::default()
             },
             ProbeConfig {
                 name: String::from("2"),
                 filter: None,
                 address: String::new(),
+                ..Default::default()
             },
         ];
-        let state = AppState::from_probes(&config);
+        let state = AppState::from_probes(&config).expect("valid probes");
         let mut app = App::new("Probe", state);
 
         // set layout to only have one tab
@@ -129,19 +609,22 @@ mod tests {
                 name: String::from("0"),
                 filter: None,
                 address: String::new(),
+                ..Default::default()
             },
             ProbeConfig {
                 name: String::from("1"),
                 filter: None,
                 address: String::new(),
+                ..Default::default()
             },
             ProbeConfig {
                 name: String::from("2"),
                 filter: None,
                 address: String::new(),
+                ..Default::default()
             },
         ];
-        let state = AppState::from_probes(&config);
+        let state = AppState::from_probes(&config).expect("valid probes");
         let mut app = App::new("Probe", state);
 
         // set probes per tab manually
@@ -162,4 +645,221 @@ mod tests {
         app.on_up();
         assert_eq!(app.selected_probe().name, String::from("0"));
     }
+
+    #[test]
+    fn edited_filter_is_validated_and_applied() {
+        let config = vec![ProbeConfig {
+            name: String::from("a"),
+            filter: Some(String::from("UNIT")),
+            address: String::from("tcp://127.0.0.1:5556"),
+            ..Default::default()
+        }];
+        let mut app = App::new(
+            "Probe",
+            AppState::from_probes(&config).expect("valid probes"),
+        );
+
+        app.on_key('e');
+        assert_eq!(app.editor.as_ref().map(|e| e.input.as_str()), Some("UNIT"));
+        app.on_key('(');
+        assert_eq!(
+            app.editor.as_ref().and_then(|e| e.error.as_deref()),
+            Some("unclosed group")
+        );
+
+        // an invalid filter can't be applied
+        app.on_key('\n');
+        assert!(app.editor.is_some());
+        assert_eq!(app.state.probes[0].filter, "UNIT");
+
+        app.on_backspace();
+        app.on_key('q');
+        app.on_tab();
+        assert!(app.editor.is_none());
+        assert!(!app.should_quit);
+        assert_eq!(app.state.probes[0].filter, "UNITq");
+        assert_eq!(
+            app.pending_save,
+            Some((String::from("a"), String::from("UNITq")))
+        );
+    }
+
+    /// An app with one probe per name, all on the same endpoint.
+    fn app(names: &[&str]) -> App<'static> {
+        let config: Vec<ProbeConfig> = names
+            .iter()
+            .map(|name| ProbeConfig {
+                name: (*name).to_string(),
+                address: String::from("tcp://127.0.0.1:5556"),
+                ..Default::default()
+            })
+            .collect();
+        App::new(
+            "Probe",
+            AppState::from_probes(&config).expect("valid probes"),
+        )
+    }
+
+    #[test]
+    fn probes_can_be_added_and_removed() {
+        let mut app = app(&["a"]);
+        app.tabs.recalculate_layout(1, 4);
+
+        app.on_key('a');
+        "a".chars().for_each(|c| app.on_key(c));
+        app.on_tab();
+        "tcp://127.0.0.1:5555".chars().for_each(|c| app.on_key(c));
+        assert_eq!(
+            app.form.as_ref().and_then(|f| f.error.as_deref()),
+            Some("probe name a is used more than once")
+        );
+
+        // fix the name and add it
+        app.on_up();
+        app.on_key('2');
+        app.on_key('\n');
+        assert!(app.form.is_none());
+        assert!(app.probes_changed);
+        let names: Vec<&str> = app.state.probes.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "a2"]);
+        assert_eq!(app.tabs.num_probes, 2);
+
+        app.on_down();
+        app.on_key('d');
+        assert_eq!(app.state.probes.len(), 1);
+        assert_eq!(app.tabs.selected_probe, 0);
+        app.on_key('d');
+        assert_eq!(app.state.probes.len(), 1);
+    }
+
+    #[test]
+    fn detail_view_stays_on_the_selected_message() {
+        let config = vec![ProbeConfig {
+            name: String::from("a"),
+            address: String::from("tcp://127.0.0.1:5556"),
+            ..Default::default()
+        }];
+        let mut app = App::new(
+            "Probe",
+            AppState::from_probes(&config).expect("valid probes"),
+        );
+        let message = |text: &str| Message::new("tcp://127.0.0.1:5556", vec![text.into()]);
+        for text in ["UNIT 1", "other", "UNIT 2", "other"] {
+            app.process_message_for_stream(&message(text));
+        }
+
+        app.on_key('\n');
+        "/UNIT\n".chars().for_each(|c| app.on_key(c));
+        assert_eq!(app.state.detail.selected, 1);
+        app.on_key('n');
+        assert_eq!(app.state.detail.selected, 3);
+
+        // a new message pushes the selection down with the message it points at
+        app.process_message_for_stream(&message("new"));
+        assert_eq!(app.state.detail.selected, 4);
+        assert_eq!(app.state.probes[0].messages()[4].text(), "UNIT 1");
+
+        // at the top, the selection follows new messages
+        app.on_page_up();
+        assert_eq!(app.state.detail.selected, 3);
+        "kkk".chars().for_each(|c| app.on_key(c));
+        app.process_message_for_stream(&message("newer"));
+        assert_eq!(app.state.detail.selected, 0);
+    }
+
+    #[test]
+    fn chart_view_takes_its_keys() {
+        let config = vec![ProbeConfig {
+            name: String::from("a"),
+            ..Default::default()
+        }];
+        let mut app = App::new(
+            "Probe",
+            AppState::from_probes(&config).expect("valid probes"),
+        );
+
+        app.on_key('c');
+        assert!(app.state.chart_view);
+        app.on_key('+');
+        assert_eq!(app.state.chart.span, 150);
+        // list view keys don't reach through
+        app.on_key('d');
+        app.on_key('\n');
+        assert_eq!(app.state.probes.len(), 1);
+        assert!(!app.state.detail_view);
+
+        app.on_escape();
+        assert!(!app.state.chart_view);
+    }
+
+    #[test]
+    fn paused_display_is_frozen_while_probes_keep_counting() {
+        let config = vec![ProbeConfig {
+            name: String::from("a"),
+            address: String::from("tcp://127.0.0.1:5556"),
+            ..Default::default()
+        }];
+        let mut app = App::new(
+            "Probe",
+            AppState::from_probes(&config).expect("valid probes"),
+        );
+        let message = || Message::new("tcp://127.0.0.1:5556", vec![b"UNIT".to_vec()]);
+        app.process_message_for_stream(&message());
+
+        app.on_key('p');
+        app.process_message_for_stream(&message());
+        app.process_message_for_stream(&message());
+        assert_eq!(app.selected_probe().count, 1);
+        assert_eq!(app.state.probes[0].count, 3);
+        assert_eq!(app.paused.as_ref().map(|p| p.new), Some(2));
+
+        // a new snapshot after adding a probe already shows those messages
+        app.on_key('a');
+        "b".chars().for_each(|c| app.on_key(c));
+        app.on_tab();
+        "tcp://127.0.0.1:5557".chars().for_each(|c| app.on_key(c));
+        app.on_key('\n');
+        assert_eq!(app.selected_probe().count, 3);
+        assert_eq!(app.paused.as_ref().map(|p| p.new), Some(0));
+
+        app.on_key('p');
+        assert!(app.paused.is_none());
+        assert_eq!(app.selected_probe().count, 3);
+    }
+
+    #[test]
+    fn messages_are_dispatched_by_endpoint_and_topic() {
+        let config = vec![
+            ProbeConfig {
+                name: String::from("all"),
+                address: String::from("tcp://127.0.0.1:5556"),
+                ..Default::default()
+            },
+            ProbeConfig {
+                name: String::from("unit"),
+                address: String::from("tcp://127.0.0.1:5556"),
+                topics: vec![String::from("UNIT")],
+                ..Default::default()
+            },
+            ProbeConfig {
+                name: String::from("other"),
+                address: String::from("tcp://127.0.0.1:5557"),
+                ..Default::default()
+            },
+        ];
+        let state = AppState::from_probes(&config).expect("valid probes");
+        let mut app = App::new("Probe", state);
+
+        app.process_message_for_stream(&Message::new(
+            "tcp://127.0.0.1:5556",
+            vec![b"UNIT".to_vec(), b"1 2 3".to_vec()],
+        ));
+        app.process_message_for_stream(&Message::new(
+            "tcp://127.0.0.1:5556",
+            vec![b"STATUS".to_vec(), b"ok".to_vec()],
+        ));
+
+        let counts: Vec<u32> = app.state.probes.iter().map(|p| p.count).collect();
+        assert_eq!(counts, vec![2, 1, 0]);
+    }
 }
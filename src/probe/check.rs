@@ -0,0 +1,133 @@
+use crate::probe::config::ProbeConfig;
+use crate::probe::inputs::{InputEvent, Inputs, MessageText};
+use crate::probe::state::{AppState, Probe};
+use std::io::{self, Write};
+use std::time::Duration;
+use tokio::time::{Instant, sleep_until};
+
+/// Connects to every probe's endpoint and listens for up to `wait`, or until every probe has
+/// received something. Prints a table of what each probe saw and returns whether all of them got
+/// traffic.
+pub async fn run(probes: &[ProbeConfig], wait: Duration) -> io::Result<bool> {
+    let mut inputs = Inputs::with_probes(probes);
+    let mut state = AppState::from_probes(probes).map_err(io::Error::other)?;
+    let mut received = vec![0; state.probes.len()];
+    let deadline = Instant::now() + wait;
+
+    while received.contains(&0) {
+        let event = tokio::select! {
+            event = inputs.next() => event,
+            () = sleep_until(deadline) => None,
+        };
+        match event {
+            Some(InputEvent::Message(msg)) => {
+                let text = MessageText::new(&msg);
+                for (probe, received) in state.probes.iter_mut().zip(&mut received) {
+                    if probe.address == msg.endpoint && probe.is_subscribed(&msg) {
+                        *received += 1;
+                        probe.process_message(&msg, &text);
+                    }
+                }
+            }
+            Some(InputEvent::State {
+                endpoint,
+                state: connection,
+            }) => {
+                for probe in state.probes.iter_mut().filter(|p| p.address == endpoint) {
+                    probe.connection = Some(connection.clone());
+                }
+            }
+            Some(InputEvent::Peers { .. } | InputEvent::Bound { .. }) => {}
+            None => break,
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    for line in table(&state.probes, &received) {
+        writeln!(out, "{line}")?;
+    }
+    Ok(!received.contains(&0))
+}
+
+/// One line per probe plus a header, with columns padded to the widest entry.
+fn table(probes: &[Probe], received: &[u64]) -> Vec<String> {
+    let header = [
+        "PROBE",
+        "ADDRESS",
+        "CONNECTION",
+        "RECEIVED",
+        "MATCHED",
+        "RESULT",
+    ]
+    .map(String::from);
+    let rows: Vec<[String; 6]> = probes
+        .iter()
+        .zip(received)
+        .map(|(p, &received)| {
+            [
+                p.name.clone(),
+                p.address.clone(),
+                p.connection
+                    .as_ref()
+                    .map_or_else(|| String::from("-"), ToString::to_string),
+                received.to_string(),
+                p.count.to_string(),
+                String::from(if received > 0 { "ok" } else { "no traffic" }),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    std::iter::once(&header)
+        .chain(&rows)
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::inputs::ConnectionState;
+
+    #[test]
+    fn table_lines_up_columns() {
+        let mut probes = AppState::from_probes(&[
+            ProbeConfig {
+                name: String::from("Units"),
+                address: String::from("tcp://127.0.0.1:5556"),
+                ..Default::default()
+            },
+            ProbeConfig {
+                name: String::from("B"),
+                address: String::from("tcp://127.0.0.1:5555"),
+                ..Default::default()
+            },
+        ])
+        .expect("valid probes")
+        .probes;
+        probes[0].connection = Some(ConnectionState::Connected);
+        probes[0].count = 3;
+
+        assert_eq!(
+            table(&probes, &[4, 0]),
+            vec![
+                "PROBE  ADDRESS               CONNECTION  RECEIVED  MATCHED  RESULT",
+                "Units  tcp://127.0.0.1:5556  connected   4         3        ok",
+                "B      tcp://127.0.0.1:5555  -           0         0        no traffic",
+            ]
+        );
+    }
+}
@@ -1,7 +1,16 @@
+use crate::probe::inputs::MessageText;
 use argh::FromArgs;
 use itertools::Itertools;
 use regex::Regex;
 use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+use toml_edit::{DocumentMut, Item};
 
 /// Probe Config
 #[derive(Debug, FromArgs)]
@@ -12,34 +21,1355 @@ pub struct Cli {
     /// config file.
     #[argh(option, default = "String::from(\"probe.toml\")")]
     pub config: String,
+    /// record all received messages to this capture file.
+    #[argh(option)]
+    pub record: Option<String>,
+    /// print matched messages to stdout instead of showing the UI.
+    #[argh(switch)]
+    pub headless: bool,
+    /// headless output format: text or json (one object per line).
+    #[argh(option, default = "OutputFormat::Text")]
+    pub format: OutputFormat,
+    /// headless: exit after this many matched messages.
+    #[argh(option)]
+    pub count: Option<u64>,
+    /// headless: exit after this many seconds.
+    #[argh(option)]
+    pub timeout: Option<u64>,
+    /// headless: exit with code 2 as soon as a probe exceeds its configured maximum silence, instead of warning.
+    #[argh(switch)]
+    pub fail_on_silence: bool,
+    #[argh(subcommand)]
+    pub command: Option<Command>,
 }
 
-#[derive(Debug, Deserialize)]
+/// How headless mode prints matched messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!("unknown format '{other}', expected text or json")),
+        }
+    }
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Replay(Replay),
+    Check(Check),
+}
+
+/// Validate the config, connect to every endpoint and report which probes see traffic.
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "check")]
+pub struct Check {
+    /// config file, overrides the global --config.
+    #[argh(option)]
+    pub config: Option<String>,
+    /// seconds to wait for traffic before giving up.
+    #[argh(option, default = "5")]
+    pub wait: u64,
+}
+
+/// Replay a capture file instead of connecting to the probes' endpoints.
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "replay")]
+pub struct Replay {
+    /// capture file written with --record.
+    #[argh(positional)]
+    pub file: String,
+    /// playback speed as a multiple of the original, e.g. 2 for twice as fast.
+    #[argh(option, default = "1.0")]
+    pub speed: f64,
+    /// replay as fast as possible, ignoring the original timing.
+    #[argh(switch)]
+    pub max_speed: bool,
+}
+
+// field names are the TOML keys
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Probes {
+    /// Default for `ProbeConfig::history`.
+    pub history: Option<usize>,
+    /// Default for `ProbeConfig::buckets`.
+    pub buckets: Option<usize>,
+    /// How many megabytes of messages all probes may keep together. The oldest messages are
+    /// dropped first when it's exceeded.
+    pub memory_budget_mb: Option<usize>,
     pub probes: Vec<ProbeConfig>,
+    #[serde(default)]
+    pub alerts: Vec<AlertConfig>,
 }
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct ProbeConfig {
     pub name: String,
     pub filter: Option<String>,
     pub address: String,
+    /// ZMQ topic prefixes to subscribe to. Empty means subscribe to everything.
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Which part of the message the filter is matched against.
+    #[serde(default)]
+    pub scope: FilterScope,
+    /// Optional boolean filter expression, applied in addition to `filter`.
+    pub expr: Option<String>,
+    /// The kind of ZMQ socket to receive with.
+    #[serde(default)]
+    pub socket_type: SocketType,
+    /// Whether to connect to `address` or bind to it and let peers connect.
+    #[serde(default)]
+    pub mode: Mode,
+    /// How many matched messages to keep for the detail view.
+    pub history: Option<usize>,
+    /// How many seconds of histogram to keep.
+    pub buckets: Option<usize>,
+    /// Seconds without a matched message after which the probe is flagged as silent.
+    pub max_silence: Option<u64>,
+}
+
+/// A rule that watches one probe. Exactly one of the conditions has to be set.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AlertConfig {
+    /// Shown in the alert log. Defaults to the probe and the condition.
+    pub name: Option<String>,
+    pub probe: String,
+    /// Fires when the probe matches more messages per second than this.
+    pub rate_above: Option<usize>,
+    /// Fires when the probe matches fewer messages per second than this.
+    pub rate_below: Option<usize>,
+    /// Fires after this many seconds without a matched message.
+    pub silence: Option<u64>,
+    /// Fires when the probe matched at least this many messages within `window`.
+    pub matches: Option<usize>,
+    /// Seconds `matches` counts over.
+    pub window: Option<u64>,
+    /// Fires when a matched message also matches this regex.
+    pub pattern: Option<String>,
+    /// Shell command to run when the alert fires, with the details in `PROBE_ALERT_*`
+    /// environment variables.
+    pub command: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    #[default]
+    Connect,
+    Bind,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Connect => "connect",
+            Mode::Bind => "bind",
+        })
+    }
+}
+
+/// The receiving ZMQ socket types a probe can use.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum SocketType {
+    #[default]
+    #[serde(alias = "SUB")]
+    Sub,
+    #[serde(alias = "XSUB")]
+    Xsub,
+    #[serde(alias = "PULL")]
+    Pull,
+    /// Receives requests without ever replying.
+    #[serde(alias = "REP")]
+    Rep,
+    /// Receives messages prefixed with the peer identity without ever replying.
+    #[serde(alias = "ROUTER")]
+    Router,
 }
 
+impl SocketType {
+    /// Whether the socket type filters by topic on the publisher side.
+    pub fn subscribes(self) -> bool {
+        matches!(self, SocketType::Sub | SocketType::Xsub)
+    }
+}
+
+impl fmt::Display for SocketType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SocketType::Sub => "SUB",
+            SocketType::Xsub => "XSUB",
+            SocketType::Pull => "PULL",
+            SocketType::Rep => "REP",
+            SocketType::Router => "ROUTER",
+        })
+    }
+}
+
+/// The part of a multipart message a probe's filter applies to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterScope {
+    /// All frames, joined by newlines.
+    #[default]
+    Message,
+    /// Only the first frame.
+    Topic,
+    /// Only the frame with the given index.
+    Frame(usize),
+}
+
+/// Everything that can be wrong with a config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    /// The file isn't valid TOML or doesn't fit the config structure. `line` and `column` are
+    /// 1-based, when toml can tell where the problem is.
+    Syntax {
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    InvalidRegex {
+        probe: String,
+        error: regex::Error,
+    },
+    InvalidExpression {
+        probe: String,
+        error: ParseError,
+    },
+    InvalidAddress {
+        probe: String,
+        address: String,
+        error: EndpointError,
+    },
+    DuplicateName(String),
+    NoProbes,
+    TopicsWithoutSubscription {
+        probe: String,
+        socket_type: SocketType,
+    },
+    /// Probes on the same address share a socket, so they have to agree on how to set it up.
+    ConflictingSocket {
+        address: String,
+        setting: &'static str,
+    },
+    OverlappingBinds(String, String),
+    /// An alert sets none or several of the conditions.
+    AlertCondition(String),
+    /// `matches = 0` would hold all the time.
+    ZeroMatches(String),
+    /// `window` only applies to `matches`.
+    WindowWithoutMatches(String),
+    UnknownAlertProbe {
+        alert: String,
+        probe: String,
+    },
+    InvalidAlertPattern {
+        alert: String,
+        error: regex::Error,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "can't read {path}: {error}"),
+            ConfigError::Syntax {
+                line: Some(line),
+                column: Some(column),
+                message,
+            } => write!(f, "line {line}, column {column}: {message}"),
+            ConfigError::Syntax { message, .. } => write!(f, "{message}"),
+            ConfigError::InvalidRegex { probe, error } => {
+                write!(f, "probe {probe}: invalid filter regex: {error}")
+            }
+            ConfigError::InvalidExpression { probe, error } => {
+                write!(f, "probe {probe}: invalid filter expression at {error}")
+            }
+            ConfigError::InvalidAddress {
+                probe,
+                address,
+                error,
+            } => write!(f, "probe {probe}: invalid address {address}: {error}"),
+            ConfigError::DuplicateName(name) => {
+                write!(f, "probe name {name} is used more than once")
+            }
+            ConfigError::NoProbes => write!(f, "no probes configured"),
+            ConfigError::TopicsWithoutSubscription { probe, socket_type } => write!(
+                f,
+                "probe {probe}: sets topics, but {socket_type} sockets don't subscribe"
+            ),
+            ConfigError::ConflictingSocket { address, setting } => {
+                write!(f, "probes on {address} use different {setting}s")
+            }
+            ConfigError::OverlappingBinds(a, b) => {
+                write!(f, "probes bind to both {a} and {b}, which overlap")
+            }
+            ConfigError::AlertCondition(alert) => write!(
+                f,
+                "alert {alert}: needs exactly one of rate_above, rate_below, silence, matches or \
+                 pattern"
+            ),
+            ConfigError::ZeroMatches(alert) => {
+                write!(f, "alert {alert}: matches must be at least 1")
+            }
+            ConfigError::WindowWithoutMatches(alert) => {
+                write!(f, "alert {alert}: sets window without matches")
+            }
+            ConfigError::UnknownAlertProbe { alert, probe } => {
+                write!(f, "alert {alert}: there is no probe named {probe}")
+            }
+            ConfigError::InvalidAlertPattern { alert, error } => {
+                write!(f, "alert {alert}: invalid pattern regex: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Probes {
-    pub fn validate(&self) {
-        self.probes.iter().for_each(ProbeConfig::validate);
+    /// Reads, parses and validates a config file.
+    pub fn load(path: &str) -> Result<Probes, Vec<ConfigError>> {
+        let config = fs::read_to_string(path).map_err(|error| {
+            vec![ConfigError::Io {
+                path: path.to_string(),
+                error,
+            }]
+        })?;
+        let probes = Probes::parse(&config).map_err(|e| vec![e])?;
+        probes.validate()?;
+        Ok(probes)
+    }
+
+    /// Parses a config. Probes without their own `history` or `buckets` get the global ones.
+    pub fn parse(config: &str) -> Result<Probes, ConfigError> {
+        let mut probes: Probes = toml::from_str(config).map_err(|e| {
+            let (line, column) = e.span().map(|span| line_column(config, span.start)).unzip();
+            ConfigError::Syntax {
+                line,
+                column,
+                message: e.message().to_string(),
+            }
+        })?;
+        for probe in &mut probes.probes {
+            probe.history = probe.history.or(probes.history);
+            probe.buckets = probe.buckets.or(probes.buckets);
+        }
+        Ok(probes)
+    }
+
+    /// The memory budget in bytes, if there is one.
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget_mb
+            .map(|mb| mb.saturating_mul(1024 * 1024))
+    }
+
+    /// Checks the whole config and returns every problem found, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors: Vec<ConfigError> =
+            self.probes.iter().flat_map(ProbeConfig::validate).collect();
+        if self.probes.is_empty() {
+            errors.push(ConfigError::NoProbes);
+        }
 
         // make sure all probe names are unique
-        let p: Vec<&String> = self.probes.iter().map(|p| &p.name).unique().collect();
-        assert!(
-            p.len() == self.probes.len(),
-            "Make sure Probe names are unique."
+        errors.extend(
+            self.probes
+                .iter()
+                .map(|p| &p.name)
+                .duplicates()
+                .map(|name| ConfigError::DuplicateName(name.clone())),
         );
+
+        // probes on the same address share a socket, so they have to agree on how to set it up
+        for (address, group) in self
+            .probes
+            .iter()
+            .into_group_map_by(|p| &p.address)
+            .into_iter()
+            .sorted_by_key(|(address, _)| *address)
+        {
+            if !group.iter().map(|p| p.socket_type).all_equal() {
+                errors.push(ConfigError::ConflictingSocket {
+                    address: address.clone(),
+                    setting: "socket type",
+                });
+            }
+            if !group.iter().map(|p| p.mode).all_equal() {
+                errors.push(ConfigError::ConflictingSocket {
+                    address: address.clone(),
+                    setting: "mode",
+                });
+            }
+        }
+
+        // different addresses can still end up on the same local endpoint, e.g. `tcp://*:5556`
+        // and `tcp://127.0.0.1:5556`, and only one socket can bind there
+        let binds: Vec<(&str, Endpoint)> = self
+            .probes
+            .iter()
+            .filter(|p| p.mode == Mode::Bind)
+            .map(|p| p.address.as_str())
+            .unique()
+            .filter_map(|a| Some((a, a.parse().ok()?)))
+            .collect();
+        for ((a, a_endpoint), (b, b_endpoint)) in binds.iter().tuple_combinations() {
+            if binds_overlap(a_endpoint, b_endpoint) {
+                errors.push(ConfigError::OverlappingBinds(
+                    (*a).to_string(),
+                    (*b).to_string(),
+                ));
+            }
+        }
+
+        for alert in &self.alerts {
+            errors.extend(alert.validate(&self.probes));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 }
+
+/// Sets the filter of the named probe in a config file, leaving the rest of the file as it is.
+pub fn save_filter(path: &str, probe: &str, filter: &str) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut config: DocumentMut = fs::read_to_string(path)?
+        .parse()
+        .map_err(|e: toml_edit::TomlError| invalid(e.message().to_string()))?;
+    let table = config
+        .get_mut("probes")
+        .and_then(Item::as_array_of_tables_mut)
+        .and_then(|probes| {
+            probes
+                .iter_mut()
+                .find(|p| p.get("name").and_then(Item::as_str) == Some(probe))
+        })
+        .ok_or_else(|| invalid(format!("no probe named {probe}")))?;
+    table["filter"] = toml_edit::value(filter);
+    fs::write(path, config.to_string())
+}
+
+/// Notices changes to the config file by polling its modification time.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &str) -> ConfigWatcher {
+        let path = PathBuf::from(path);
+        let modified = modified(&path);
+        ConfigWatcher { path, modified }
+    }
+
+    /// Whether the file was modified since the watcher was created or last reported a change.
+    pub fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 1-based line and column of a byte offset.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+/// A ZMQ endpoint in one of the transports zeromq supports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(Host, u16),
+    Ipc(PathBuf),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Host {
+    /// `*`, all interfaces. Only valid for binding.
+    Wildcard,
+    Ip(IpAddr),
+    Name(String),
+}
+
+impl Host {
+    fn is_wildcard(&self) -> bool {
+        match self {
+            Host::Wildcard => true,
+            Host::Ip(ip) => ip.is_unspecified(),
+            Host::Name(_) => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EndpointError {
+    MissingScheme,
+    UnsupportedScheme(String),
+    MissingPort,
+    InvalidPort(String),
+    InvalidHost(String),
+    EmptyPath,
+    /// Wildcard hosts and port 0 only make sense when binding.
+    BindOnly(&'static str),
+}
+
+impl fmt::Display for EndpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EndpointError::MissingScheme => write!(f, "missing scheme, e.g. tcp://"),
+            EndpointError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported scheme {scheme}://, use tcp:// or ipc://")
+            }
+            EndpointError::MissingPort => write!(f, "missing port, use host:port"),
+            EndpointError::InvalidPort(port) => {
+                write!(f, "invalid port '{port}', must be between 0 and 65535")
+            }
+            EndpointError::InvalidHost(host) => write!(f, "invalid host '{host}'"),
+            EndpointError::EmptyPath => write!(f, "missing ipc path"),
+            EndpointError::BindOnly(what) => write!(f, "{what} only works with mode = \"bind\""),
+        }
+    }
+}
+
+impl std::error::Error for EndpointError {}
+
+impl FromStr for Endpoint {
+    type Err = EndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once("://").ok_or(EndpointError::MissingScheme)?;
+        match scheme {
+            "tcp" => {
+                let (host, port) = rest.rsplit_once(':').ok_or(EndpointError::MissingPort)?;
+                let port = port
+                    .parse()
+                    .map_err(|_| EndpointError::InvalidPort(port.to_string()))?;
+                Ok(Endpoint::Tcp(host.parse()?, port))
+            }
+            "ipc" if rest.is_empty() => Err(EndpointError::EmptyPath),
+            "ipc" => Ok(Endpoint::Ipc(PathBuf::from(rest))),
+            scheme => Err(EndpointError::UnsupportedScheme(scheme.to_string())),
+        }
+    }
+}
+
+impl FromStr for Host {
+    type Err = EndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || EndpointError::InvalidHost(s.to_string());
+        if s == "*" {
+            return Ok(Host::Wildcard);
+        }
+        if let Some(ipv6) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            return ipv6.parse().map(Host::Ip).map_err(|_| invalid());
+        }
+        if let Ok(ip) = s.parse() {
+            return Ok(Host::Ip(ip));
+        }
+        let is_name = !s.is_empty()
+            && s.split('.').all(|label| {
+                !label.is_empty()
+                    && !label.starts_with('-')
+                    && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+            });
+        if is_name {
+            Ok(Host::Name(s.to_string()))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl Endpoint {
+    /// Parses an address and checks it makes sense for the mode.
+    pub fn parse_for(address: &str, mode: Mode) -> Result<Endpoint, EndpointError> {
+        let endpoint: Endpoint = address.parse()?;
+        if mode == Mode::Connect {
+            match &endpoint {
+                Endpoint::Tcp(Host::Wildcard, _) => {
+                    return Err(EndpointError::BindOnly("a wildcard host"));
+                }
+                Endpoint::Tcp(_, 0) => return Err(EndpointError::BindOnly("port 0")),
+                _ => {}
+            }
+        }
+        Ok(endpoint)
+    }
+}
+
+/// Whether binding to both endpoints would fight over the same local address.
+fn binds_overlap(a: &Endpoint, b: &Endpoint) -> bool {
+    match (a, b) {
+        // port 0 picks a free port, so those never collide
+        (Endpoint::Tcp(a_host, a_port), Endpoint::Tcp(b_host, b_port)) => {
+            a_port == b_port
+                && *a_port != 0
+                && (a_host == b_host || a_host.is_wildcard() || b_host.is_wildcard())
+        }
+        (Endpoint::Ipc(a_path), Endpoint::Ipc(b_path)) => a_path == b_path,
+        _ => false,
+    }
+}
+
 impl ProbeConfig {
-    fn validate(&self) {
-        // make sure Filter is a valid regex
-        Regex::new(self.filter.as_ref().unwrap_or(&".*".to_string())).expect("Invalid regex");
+    fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if let Some(filter) = &self.filter {
+            if let Err(error) = Regex::new(filter) {
+                errors.push(ConfigError::InvalidRegex {
+                    probe: self.name.clone(),
+                    error,
+                });
+            }
+        }
+
+        if let Some(expr) = &self.expr {
+            if let Err(error) = FilterExpr::parse(expr) {
+                errors.push(ConfigError::InvalidExpression {
+                    probe: self.name.clone(),
+                    error,
+                });
+            }
+        }
+
+        if let Err(error) = Endpoint::parse_for(&self.address, self.mode) {
+            errors.push(ConfigError::InvalidAddress {
+                probe: self.name.clone(),
+                address: self.address.clone(),
+                error,
+            });
+        }
+
+        if !self.topics.is_empty() && !self.socket_type.subscribes() {
+            errors.push(ConfigError::TopicsWithoutSubscription {
+                probe: self.name.clone(),
+                socket_type: self.socket_type,
+            });
+        }
+
+        errors
+    }
+}
+
+impl AlertConfig {
+    /// What to call the alert in errors: its name, or the probe it watches.
+    pub fn label(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("on {}", self.probe))
+    }
+
+    fn validate(&self, probes: &[ProbeConfig]) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        let conditions = [
+            self.rate_above.is_some(),
+            self.rate_below.is_some(),
+            self.silence.is_some(),
+            self.matches.is_some(),
+            self.pattern.is_some(),
+        ];
+        if conditions.iter().filter(|set| **set).count() != 1 {
+            errors.push(ConfigError::AlertCondition(self.label()));
+        }
+        if self.matches == Some(0) {
+            errors.push(ConfigError::ZeroMatches(self.label()));
+        }
+        if self.window.is_some() && self.matches.is_none() {
+            errors.push(ConfigError::WindowWithoutMatches(self.label()));
+        }
+
+        if !probes.iter().any(|p| p.name == self.probe) {
+            errors.push(ConfigError::UnknownAlertProbe {
+                alert: self.label(),
+                probe: self.probe.clone(),
+            });
+        }
+
+        if let Some(pattern) = &self.pattern {
+            if let Err(error) = Regex::new(pattern) {
+                errors.push(ConfigError::InvalidAlertPattern {
+                    alert: self.label(),
+                    error,
+                });
+            }
+        }
+
+        errors
+    }
+}
+
+/// A boolean filter expression, e.g. `topic starts "UNIT" AND contains "ERROR" AND NOT
+/// "heartbeat"`.
+///
+/// Grammar, with keywords case-insensitive:
+///
+/// ```text
+/// expr     := and ("OR" and)*
+/// and      := unary ("AND" unary)*
+/// unary    := "NOT" unary | "(" expr ")" | term
+/// term     := selector? op? string
+/// selector := "message" | "topic" | "frame" "[" number "]"
+/// op       := "matches" | "contains" | "starts" | "is"
+/// ```
+///
+/// The selector defaults to `message` and the op to `contains`. `matches` takes a regex, the
+/// others a literal. Strings are in double or single quotes; inside them `\` only escapes the quote
+/// char and itself.
+#[derive(Clone, Debug)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Term(FilterScope, Matcher),
+}
+
+/// How a single term compares the selected text.
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    Matches(Regex),
+    Contains(String),
+    StartsWith(String),
+    Is(String),
+}
+
+/// A filter expression syntax error. `column` is 1-based and counts chars.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FilterExpr {
+    pub fn parse(input: &str) -> Result<FilterExpr, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            end: input.chars().count() + 1,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(t) => Err(t.error(format!("unexpected {}", t.kind))),
+        }
+    }
+
+    pub fn eval(&self, text: &MessageText) -> bool {
+        match self {
+            FilterExpr::And(a, b) => a.eval(text) && b.eval(text),
+            FilterExpr::Or(a, b) => a.eval(text) || b.eval(text),
+            FilterExpr::Not(a) => !a.eval(text),
+            FilterExpr::Term(scope, matcher) => text
+                .scoped(*scope)
+                .is_some_and(|text| matcher.is_match(&text)),
+        }
+    }
+}
+
+impl Matcher {
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Matches(re) => re.is_match(text),
+            Matcher::Contains(s) => text.contains(s.as_str()),
+            Matcher::StartsWith(s) => text.starts_with(s.as_str()),
+            Matcher::Is(s) => text == s,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Word(String),
+    Str(String),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::LParen => write!(f, "'('"),
+            TokenKind::RParen => write!(f, "')'"),
+            TokenKind::LBracket => write!(f, "'['"),
+            TokenKind::RBracket => write!(f, "']'"),
+            TokenKind::Word(w) => write!(f, "'{w}'"),
+            TokenKind::Str(s) => write!(f, "string \"{s}\""),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            column: self.column,
+            message,
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    // track columns in chars, not bytes, so they line up with what the user typed
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        let column = i + 1;
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            '"' | '\'' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        // only quotes and backslashes are escaped, so regexes like `\d` survive
+                        Some((_, '\\')) => match chars.next_if(|(_, e)| *e == c || *e == '\\') {
+                            Some((_, escaped)) => s.push(escaped),
+                            None => s.push('\\'),
+                        },
+                        Some((_, q)) if q == c => break,
+                        Some((_, other)) => s.push(other),
+                        None => {
+                            return Err(ParseError {
+                                column,
+                                message: String::from("unterminated string"),
+                            });
+                        }
+                    }
+                }
+                TokenKind::Str(s)
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut w = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    w.push(c);
+                }
+                TokenKind::Word(w)
+            }
+            other => {
+                return Err(ParseError {
+                    column,
+                    message: format!("unexpected character '{other}'"),
+                });
+            }
+        };
+        tokens.push(Token { kind, column });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// column just past the input, for errors at the end
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, ParseError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(ParseError {
+            column: self.end,
+            message: format!("expected {expected}, found end of expression"),
+        })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_some_and(|t| t.is_keyword(keyword)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<FilterExpr, ParseError> {
+        let mut expr = self.and()?;
+        while self.eat_keyword("or") {
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<FilterExpr, ParseError> {
+        let mut expr = self.unary()?;
+        while self.eat_keyword("and") {
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<FilterExpr, ParseError> {
+        if self.eat_keyword("not") {
+            return Ok(FilterExpr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek().is_some_and(|t| t.kind == TokenKind::LParen) {
+            self.pos += 1;
+            let expr = self.or()?;
+            let close = self.next("')'")?;
+            if close.kind != TokenKind::RParen {
+                return Err(close.error(format!("expected ')', found {}", close.kind)));
+            }
+            return Ok(expr);
+        }
+        self.term()
+    }
+
+    fn term(&mut self) -> Result<FilterExpr, ParseError> {
+        let scope = self.selector()?;
+        let op = match self.peek() {
+            Some(
+                t @ Token {
+                    kind: TokenKind::Word(w),
+                    ..
+                },
+            ) => Some((w.to_ascii_lowercase(), t.clone())),
+            _ => None,
+        };
+        if op.is_some() {
+            self.pos += 1;
+        }
+
+        let token = self.next("a string")?;
+        let TokenKind::Str(s) = token.kind.clone() else {
+            return Err(token.error(format!("expected a string, found {}", token.kind)));
+        };
+
+        let matcher = match op {
+            None => Matcher::Contains(s),
+            Some((op, op_token)) => match op.as_str() {
+                "matches" => Matcher::Matches(
+                    Regex::new(&s).map_err(|e| token.error(format!("invalid regex: {e}")))?,
+                ),
+                "contains" => Matcher::Contains(s),
+                "starts" => Matcher::StartsWith(s),
+                "is" => Matcher::Is(s),
+                _ => {
+                    return Err(op_token.error(format!("unknown operator {}", op_token.kind)));
+                }
+            },
+        };
+        Ok(FilterExpr::Term(scope, matcher))
+    }
+
+    fn selector(&mut self) -> Result<FilterScope, ParseError> {
+        let Some(token) = self.peek().cloned() else {
+            return Ok(FilterScope::Message);
+        };
+        if token.is_keyword("message") {
+            self.pos += 1;
+            Ok(FilterScope::Message)
+        } else if token.is_keyword("topic") {
+            self.pos += 1;
+            Ok(FilterScope::Topic)
+        } else if token.is_keyword("frame") {
+            self.pos += 1;
+            let open = self.next("'['")?;
+            if open.kind != TokenKind::LBracket {
+                return Err(open.error(format!("expected '[', found {}", open.kind)));
+            }
+            let index = self.next("a frame index")?;
+            let TokenKind::Word(n) = &index.kind else {
+                return Err(index.error(format!("expected a frame index, found {}", index.kind)));
+            };
+            let n = n
+                .parse()
+                .map_err(|_| index.error(format!("invalid frame index '{n}'")))?;
+            let close = self.next("']'")?;
+            if close.kind != TokenKind::RBracket {
+                return Err(close.error(format!("expected ']', found {}", close.kind)));
+            }
+            Ok(FilterScope::Frame(n))
+        } else {
+            Ok(FilterScope::Message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::inputs::Message;
+
+    fn message(frames: &[&str]) -> Message {
+        Message::new("", frames.iter().map(|f| f.as_bytes().to_vec()).collect())
+    }
+
+    fn eval(expr: &str, msg: &Message) -> bool {
+        FilterExpr::parse(expr)
+            .expect("expression should parse")
+            .eval(&MessageText::new(msg))
+    }
+
+    fn probes(probes: &[(&str, Mode)]) -> Probes {
+        Probes {
+            probes: probes
+                .iter()
+                .enumerate()
+                .map(|(i, (address, mode))| ProbeConfig {
+                    name: i.to_string(),
+                    address: (*address).to_string(),
+                    mode: *mode,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn probes_can_share_a_bind_address() {
+        let result = probes(&[
+            ("tcp://*:5556", Mode::Bind),
+            ("tcp://*:5556", Mode::Bind),
+            ("tcp://*:5557", Mode::Bind),
+            ("tcp://127.0.0.1:5558", Mode::Bind),
+            ("tcp://127.0.0.2:5558", Mode::Bind),
+            ("tcp://127.0.0.1:5557", Mode::Connect),
+        ])
+        .validate();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn wildcard_bind_overlaps_specific_host() {
+        let errors = probes(&[
+            ("tcp://*:5556", Mode::Bind),
+            ("tcp://127.0.0.1:5556", Mode::Bind),
+        ])
+        .validate()
+        .expect_err("binds overlap");
+        assert!(matches!(
+            errors.as_slice(),
+            [ConfigError::OverlappingBinds(_, _)]
+        ));
+    }
+
+    #[test]
+    fn shared_address_needs_same_mode() {
+        let errors = probes(&[
+            ("tcp://127.0.0.1:5556", Mode::Bind),
+            ("tcp://127.0.0.1:5556", Mode::Connect),
+        ])
+        .validate()
+        .expect_err("modes conflict");
+        assert!(matches!(
+            errors.as_slice(),
+            [ConfigError::ConflictingSocket {
+                setting: "mode",
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn at_least_one_probe_is_needed() {
+        let errors = Probes::parse("probes = []")
+            .expect("valid TOML")
+            .validate()
+            .expect_err("no probes");
+        assert!(matches!(errors.as_slice(), [ConfigError::NoProbes]));
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let config = r#"
+            [[probes]]
+            name = "a"
+            address = "tcp://127.0.0.1:5556"
+            filter = "("
+
+            [[probes]]
+            name = "a"
+            address = "tcp:/127.0.0.1:5556"
+            expr = 'topic is "UNIT'
+
+            [[probes]]
+            name = "b"
+            address = "tcp://127.0.0.1:5557"
+            socket_type = "pull"
+            topics = ["UNIT"]
+
+            [[alerts]]
+            name = "never quiet"
+            probe = "b"
+            matches = 0
+
+            [[alerts]]
+            name = "busy"
+            probe = "b"
+            rate_above = 10
+            window = 30
+        "#;
+        let errors = Probes::parse(config)
+            .expect("valid TOML")
+            .validate()
+            .expect_err("invalid config");
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors.len(), 7, "{errors:#?}");
+        assert!(errors[0].starts_with("probe a: invalid filter regex"));
+        assert!(errors[1].starts_with("probe a: invalid filter expression at column 10"));
+        assert_eq!(
+            errors[2],
+            "probe a: invalid address tcp:/127.0.0.1:5556: missing scheme, e.g. tcp://"
+        );
+        assert!(errors[3].starts_with("probe b: sets topics"));
+        assert_eq!(errors[4], "probe name a is used more than once");
+        assert_eq!(errors[5], "alert never quiet: matches must be at least 1");
+        assert_eq!(errors[6], "alert busy: sets window without matches");
+    }
+
+    #[test]
+    fn endpoints() {
+        let parse = |s: &str| s.parse::<Endpoint>();
+        assert_eq!(
+            parse("tcp://127.0.0.1:5556"),
+            Ok(Endpoint::Tcp(Host::Ip([127, 0, 0, 1].into()), 5556))
+        );
+        assert_eq!(
+            parse("tcp://[::1]:5556"),
+            Ok(Endpoint::Tcp(
+                Host::Ip("::1".parse().expect("valid ip")),
+                5556
+            ))
+        );
+        assert_eq!(
+            parse("tcp://bus-1.example.com:80"),
+            Ok(Endpoint::Tcp(
+                Host::Name(String::from("bus-1.example.com")),
+                80
+            ))
+        );
+        assert_eq!(parse("tcp://*:0"), Ok(Endpoint::Tcp(Host::Wildcard, 0)));
+        assert_eq!(
+            parse("ipc:///tmp/probe.sock"),
+            Ok(Endpoint::Ipc(PathBuf::from("/tmp/probe.sock")))
+        );
+
+        assert_eq!(
+            parse("tcp:/127.0.0.1:5556"),
+            Err(EndpointError::MissingScheme)
+        );
+        assert_eq!(
+            parse("inproc://bus"),
+            Err(EndpointError::UnsupportedScheme(String::from("inproc")))
+        );
+        assert_eq!(parse("tcp://127.0.0.1"), Err(EndpointError::MissingPort));
+        assert_eq!(
+            parse("tcp://127.0.0.1:65536"),
+            Err(EndpointError::InvalidPort(String::from("65536")))
+        );
+        assert_eq!(
+            parse("tcp://bad host:1"),
+            Err(EndpointError::InvalidHost(String::from("bad host")))
+        );
+        assert_eq!(parse("ipc://"), Err(EndpointError::EmptyPath));
+    }
+
+    #[test]
+    fn wildcards_only_bind() {
+        assert!(Endpoint::parse_for("tcp://*:5556", Mode::Bind).is_ok());
+        assert!(Endpoint::parse_for("tcp://*:5556", Mode::Connect).is_err());
+        assert!(Endpoint::parse_for("tcp://127.0.0.1:0", Mode::Connect).is_err());
+    }
+
+    #[test]
+    fn save_filter_only_touches_that_probe() {
+        let path = std::env::temp_dir().join(format!("probe-save-{}.toml", std::process::id()));
+        let config = "# probes\n[[probes]]\nname = \"a\"\naddress = \"tcp://127.0.0.1:5556\"\nfilter = \"x\"  # old\n\n[[probes]]\nname = \"b\"\naddress = \"tcp://127.0.0.1:5556\"\n";
+        fs::write(&path, config).expect("write config");
+        let path = path.to_str().expect("utf-8 path");
+
+        save_filter(path, "b", "UNIT \\d+").expect("save filter");
+        let saved = fs::read_to_string(path).expect("read config");
+        assert!(saved.starts_with("# probes\n"));
+        assert!(saved.contains("filter = \"x\"  # old"));
+        let probes = Probes::parse(&saved).expect("valid config");
+        assert_eq!(probes.probes[1].filter.as_deref(), Some("UNIT \\d+"));
+
+        assert!(save_filter(path, "c", "x").is_err());
+        fs::remove_file(path).expect("remove config");
+    }
+
+    #[test]
+    fn probes_inherit_global_limits() {
+        let probes = Probes::parse(
+            r#"
+            history = 500
+            memory_budget_mb = 64
+
+            [[probes]]
+            name = "a"
+            address = "tcp://127.0.0.1:5556"
+
+            [[probes]]
+            name = "b"
+            address = "tcp://127.0.0.1:5556"
+            history = 0
+            buckets = 60
+            "#,
+        )
+        .expect("valid config");
+        let limits: Vec<_> = probes
+            .probes
+            .iter()
+            .map(|p| (p.history, p.buckets))
+            .collect();
+        assert_eq!(limits, vec![(Some(500), None), (Some(0), Some(60))]);
+        assert_eq!(probes.memory_budget(), Some(64 * 1024 * 1024));
+    }
+
+    #[test]
+    fn alerts_need_one_condition_and_a_known_probe() {
+        let config = r#"
+            [[probes]]
+            name = "a"
+            address = "tcp://127.0.0.1:5556"
+
+            [[alerts]]
+            probe = "a"
+            rate_above = 100
+
+            [[alerts]]
+            name = "both"
+            probe = "a"
+            rate_above = 100
+            silence = 10
+
+            [[alerts]]
+            probe = "b"
+            pattern = "("
+        "#;
+        let errors = Probes::parse(config)
+            .expect("valid TOML")
+            .validate()
+            .expect_err("invalid alerts");
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors.len(), 3, "{errors:#?}");
+        assert!(errors[0].starts_with("alert both: needs exactly one of"));
+        assert_eq!(errors[1], "alert on b: there is no probe named b");
+        assert!(errors[2].starts_with("alert on b: invalid pattern regex"));
+    }
+
+    #[test]
+    fn syntax_errors_have_a_position() {
+        let error = Probes::parse("[[probes]]\nname = \"a\"\naddress = 5556\n")
+            .expect_err("address is not a string");
+        assert!(matches!(
+            error,
+            ConfigError::Syntax {
+                line: Some(3),
+                column: Some(11),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn bare_string_is_message_contains() {
+        let msg = message(&["UNIT", "10002 45 12"]);
+        assert!(eval(r#""45""#, &msg));
+        assert!(!eval(r#""ERROR""#, &msg));
+    }
+
+    #[test]
+    fn selectors_and_operators() {
+        let msg = message(&["UNIT", "10002 45 12"]);
+        assert!(eval(r#"topic is "UNIT""#, &msg));
+        assert!(!eval(r#"topic is "UNI""#, &msg));
+        assert!(eval(r#"topic starts "UN""#, &msg));
+        assert!(eval(r"frame[1] matches '^\d+ 45'", &msg));
+        assert!(!eval(r#"frame[2] contains "1""#, &msg));
+        assert!(eval(
+            r#"message contains "UNIT
+1""#,
+            &message(&["UNIT
+1"])
+        ));
+    }
+
+    #[test]
+    fn boolean_operators_and_precedence() {
+        let msg = message(&["UNIT", "ERROR disk full"]);
+        assert!(eval(
+            r#"topic starts "UNIT" AND "ERROR" AND NOT "heartbeat""#,
+            &msg
+        ));
+        assert!(!eval(r#"NOT topic is "UNIT""#, &msg));
+        // AND binds tighter than OR
+        assert!(eval(r#""nope" AND "nope" OR "ERROR""#, &msg));
+        assert!(!eval(r#""nope" AND ("nope" OR "ERROR")"#, &msg));
+        assert!(eval(r#"not "nope" and ("x" or topic is "UNIT")"#, &msg));
+    }
+
+    #[test]
+    fn errors_report_column() {
+        let err = |expr: &str| FilterExpr::parse(expr).expect_err("expression should not parse");
+
+        assert_eq!(err(r#"topic is "UNIT"#).column, 10);
+        assert_eq!(err(r#"topic is "UNIT" AND"#).column, 20);
+        assert_eq!(err(r#"topic equals "UNIT""#).column, 7);
+        assert_eq!(err(r#"frame[x] is "a""#).column, 7);
+        assert_eq!(err(r#"matches "(""#).column, 9);
+        assert_eq!(err(r#"("a" OR "b""#).column, 12);
+        assert_eq!(err(r#""a" "b""#).column, 5);
+        assert_eq!(err(r#""a" & "b""#).column, 5);
     }
 }
@@ -0,0 +1,254 @@
+use crate::probe::app::App;
+use crate::probe::config::{Cli, OutputFormat};
+use crate::probe::inputs::{InputEvent, Inputs, Message};
+use serde_json::json;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::time::{Instant, interval, sleep_until};
+
+/// How a headless run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Matched,
+    NothingMatched,
+    /// A probe exceeded its `max_silence` and `--fail-on-silence` was given.
+    Silent,
+}
+
+/// Runs the probes without a UI and prints every match to `out`, one line per matching probe.
+/// Stops after `--count` matched messages, after `--timeout` or when the inputs run dry. Probes
+/// that go silent for longer than their `max_silence` are reported on stderr, or end the run with
+/// `--fail-on-silence`.
+pub async fn run(
+    mut inputs: Inputs,
+    mut app: App<'_>,
+    cli: &Cli,
+    out: &mut impl Write,
+) -> io::Result<Outcome> {
+    let deadline = cli
+        .timeout
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    let mut matched = 0;
+    let mut ticks = interval(Duration::from_secs(1));
+    let mut silent = HashSet::new();
+
+    while cli.count.is_none_or(|count| matched < count) {
+        let event = tokio::select! {
+            event = inputs.next() => event,
+            _ = ticks.tick() => {
+                app.on_tick();
+                report_alerts(&mut app)?;
+                if report_silence(&app, &mut silent)? && cli.fail_on_silence {
+                    return Ok(Outcome::Silent);
+                }
+                continue;
+            }
+            () = until(deadline) => None,
+        };
+        let Some(event) = event else {
+            break;
+        };
+        let InputEvent::Message(msg) = event else {
+            continue;
+        };
+
+        let probes = app.process_message_for_stream(&msg);
+        report_alerts(&mut app)?;
+        if probes.is_empty() {
+            continue;
+        }
+        matched += 1;
+        for probe in probes {
+            match cli.format {
+                OutputFormat::Text => writeln!(out, "{}", text_line(&probe, &msg))?,
+                OutputFormat::Json => writeln!(out, "{}", json_line(&probe, &msg))?,
+            }
+        }
+        out.flush()?;
+    }
+    Ok(if matched > 0 {
+        Outcome::Matched
+    } else {
+        Outcome::NothingMatched
+    })
+}
+
+/// Prints alerts that fired to stderr and starts their commands.
+fn report_alerts(app: &mut App<'_>) -> io::Result<()> {
+    let mut err = io::stderr().lock();
+    for fired in app.state.alerts.take_fired() {
+        writeln!(err, "alert {}", fired.line())?;
+        if let Err(e) = fired.run_command() {
+            writeln!(err, "could not run command of alert {}: {e}", fired.alert)?;
+        }
+    }
+    Ok(())
+}
+
+/// Warns on stderr once when a probe goes silent and again when it recovers. `silent` holds the
+/// probes already warned about. Returns whether a probe newly went silent.
+fn report_silence(app: &App<'_>, silent: &mut HashSet<String>) -> io::Result<bool> {
+    let mut err = io::stderr().lock();
+    let mut newly_silent = false;
+    for probe in &app.state.probes {
+        match probe.silent_for {
+            Some(silence) if silent.insert(probe.name.clone()) => {
+                writeln!(
+                    err,
+                    "probe {} silent for {}s",
+                    probe.name,
+                    silence.as_secs()
+                )?;
+                newly_silent = true;
+            }
+            None if silent.remove(&probe.name) => {
+                writeln!(err, "probe {} receiving again", probe.name)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(newly_silent)
+}
+
+async fn until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn text_line(probe: &str, msg: &Message) -> String {
+    let frames: Vec<String> = (0..msg.frames.len()).map(|i| msg.frame_text(i)).collect();
+    format!("{} [{probe}] {}", msg.timestamp(), frames.join(" | "))
+}
+
+/// Frames are strings when they're valid UTF-8 and hex otherwise, like in the detail view.
+fn json_line(probe: &str, msg: &Message) -> String {
+    let timestamp = msg
+        .received
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let frames: Vec<String> = (0..msg.frames.len()).map(|i| msg.frame_text(i)).collect();
+    json!({
+        "probe": probe,
+        "endpoint": msg.endpoint,
+        "timestamp": timestamp,
+        "frames": frames,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::config::ProbeConfig;
+    use crate::probe::record::Recorder;
+    use crate::probe::state::AppState;
+    use std::path::Path;
+
+    const ENDPOINT: &str = "tcp://127.0.0.1:5556";
+
+    fn message(frames: &[&[u8]]) -> Message {
+        Message {
+            endpoint: String::from(ENDPOINT),
+            received: UNIX_EPOCH + Duration::from_millis(1_700_000_000_500),
+            frames: frames.iter().map(|f| f.to_vec()).collect(),
+        }
+    }
+
+    fn cli(count: Option<u64>) -> Cli {
+        Cli {
+            tick_rate: 1000,
+            config: String::from("probe.toml"),
+            record: None,
+            headless: true,
+            format: OutputFormat::Text,
+            count,
+            timeout: None,
+            fail_on_silence: false,
+            command: None,
+        }
+    }
+
+    /// Replays `texts` as fast as possible through a single probe with `filter`, returning how
+    /// the run ended and what it printed.
+    async fn replay(texts: &[&str], filter: &str, count: Option<u64>) -> (Outcome, String) {
+        let path = std::env::temp_dir().join(format!(
+            "probe-headless-{}-{filter}-{count:?}.cap",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let mut recorder = Recorder::create(&path).expect("create capture");
+        for text in texts {
+            recorder
+                .write(&message(&[text.as_bytes()]))
+                .expect("record");
+        }
+        drop(recorder);
+
+        let inputs = Inputs::from_capture(Path::new(&path), None).expect("open capture");
+        let app = App::new(
+            "Probe",
+            AppState::from_probes(&[ProbeConfig {
+                name: String::from("units"),
+                address: String::from(ENDPOINT),
+                filter: Some(filter.to_string()),
+                ..Default::default()
+            }])
+            .expect("valid probes"),
+        );
+        let mut out = Vec::new();
+        let outcome = run(inputs, app, &cli(count), &mut out)
+            .await
+            .expect("headless run");
+        std::fs::remove_file(&path).expect("remove capture");
+        (outcome, String::from_utf8(out).expect("utf-8 output"))
+    }
+
+    #[test]
+    fn text_lines_show_every_frame() {
+        let msg = message(&[b"UNIT", b"10002 45 12", &[0, 255]]);
+        assert_eq!(
+            text_line("units", &msg),
+            "22:13:20.500 [units] UNIT | 10002 45 12 | 00ff"
+        );
+    }
+
+    #[test]
+    fn json_lines_have_hex_for_binary_frames() {
+        let msg = message(&[b"UNIT", &[0, 159, 255]]);
+        let line: serde_json::Value =
+            serde_json::from_str(&json_line("units", &msg)).expect("valid JSON");
+        assert_eq!(
+            line,
+            json!({
+                "probe": "units",
+                "endpoint": ENDPOINT,
+                "timestamp": 1_700_000_000.5,
+                "frames": ["UNIT", "009fff"],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn count_stops_the_run() {
+        let texts = ["UNIT 1", "other", "UNIT 2"];
+        let (outcome, out) = replay(&texts, "UNIT", Some(1)).await;
+        assert_eq!(outcome, Outcome::Matched);
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.ends_with("[units] UNIT 1\n"), "{out}");
+
+        let (outcome, out) = replay(&texts, "UNIT", None).await;
+        assert_eq!(outcome, Outcome::Matched);
+        assert_eq!(out.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn nothing_matched_when_the_filter_never_matches() {
+        let (outcome, out) = replay(&["UNIT 1", "other"], "ERROR", None).await;
+        assert_eq!(outcome, Outcome::NothingMatched);
+        assert!(out.is_empty());
+    }
+}
@@ -1,39 +1,395 @@
 mod zmq;
-use crate::probe::config::ProbeConfig;
-use crate::probe::inputs::zmq::ZMQInput;
+use crate::probe::config::{FilterScope, Mode, ProbeConfig, SocketType};
+use crate::probe::inputs::zmq::spawn_input;
+use crate::probe::record::{CaptureReader, Recorder};
+use itertools::Itertools;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::error;
+use zeromq::{PullSocket, RepSocket, RouterSocket, Socket, SocketRecv, SubSocket, ZmqResult};
 
-pub type Message = (String, String);
+/// Replays never wait longer than this between two messages, e.g. across a gap between two
+/// recording sessions in the same capture.
+const MAX_REPLAY_DELAY: Duration = Duration::from_secs(3600);
+
+/// A received multipart message, tagged with the endpoint it arrived on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub endpoint: String,
+    pub received: SystemTime,
+    pub frames: Vec<Vec<u8>>,
+}
+
+impl Message {
+    pub fn new(endpoint: &str, frames: Vec<Vec<u8>>) -> Message {
+        Message {
+            endpoint: endpoint.to_string(),
+            received: SystemTime::now(),
+            frames,
+        }
+    }
+
+    /// The first frame, which by PUB/SUB convention holds the topic.
+    pub fn topic(&self) -> &[u8] {
+        self.frames.first().map_or(&[], Vec::as_slice)
+    }
+
+    /// Total payload size in bytes over all frames.
+    pub fn size(&self) -> usize {
+        self.frames.iter().map(Vec::len).sum()
+    }
+
+    /// Roughly how much memory the message takes up, including its allocations.
+    pub fn memory(&self) -> usize {
+        size_of::<Message>()
+            + self.endpoint.len()
+            + self.frames.len() * size_of::<Vec<u8>>()
+            + self.size()
+    }
+
+    /// All frames joined by newlines, with invalid UTF-8 replaced. Only use this for matching and
+    /// display, never to get at the original bytes. Single-frame UTF-8 messages are borrowed.
+    pub fn text(&self) -> Cow<'_, str> {
+        match self.frames.as_slice() {
+            [] => Cow::Borrowed(""),
+            [frame] => String::from_utf8_lossy(frame),
+            frames => Cow::Owned(frames.iter().map(|f| String::from_utf8_lossy(f)).join("\n")),
+        }
+    }
+
+    /// The text of a single frame with invalid UTF-8 replaced, if the frame exists.
+    pub fn frame_lossy(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.frames.get(index).map(|f| String::from_utf8_lossy(f))
+    }
+
+    /// The part of the message a filter scope refers to, or `None` if the message doesn't have it.
+    pub fn scoped(&self, scope: FilterScope) -> Option<Cow<'_, str>> {
+        match scope {
+            FilterScope::Message => Some(self.text()),
+            FilterScope::Topic => self.frame_lossy(0),
+            FilterScope::Frame(index) => self.frame_lossy(index),
+        }
+    }
+
+    /// A printable version of a single frame: the text if it is valid UTF-8, hex otherwise.
+    pub fn frame_text(&self, index: usize) -> String {
+        let frame = &self.frames[index];
+        match std::str::from_utf8(frame) {
+            Ok(s) => s.to_string(),
+            Err(_) => frame.iter().fold(String::new(), |mut out, b| {
+                let _ = write!(out, "{b:02x}");
+                out
+            }),
+        }
+    }
+
+    /// Receive time as `HH:MM:SS.mmm` UTC.
+    pub fn timestamp(&self) -> String {
+        timestamp(self.received)
+    }
+}
+
+/// A message's frames joined by newlines, built the first time something asks for them. Probes
+/// and alerts that only look at the topic or a single frame never pay for joining the frames, and
+/// the ones that do share one copy.
+pub struct MessageText<'a> {
+    msg: &'a Message,
+    joined: OnceCell<Cow<'a, str>>,
+}
+
+impl<'a> MessageText<'a> {
+    pub fn new(msg: &'a Message) -> MessageText<'a> {
+        MessageText {
+            msg,
+            joined: OnceCell::new(),
+        }
+    }
+
+    /// `Message::text`, built on first use.
+    pub fn joined(&self) -> &str {
+        self.joined.get_or_init(|| self.msg.text())
+    }
+
+    /// `Message::scoped`, reusing the joined text for the whole message.
+    pub fn scoped(&self, scope: FilterScope) -> Option<Cow<'_, str>> {
+        match scope {
+            FilterScope::Message => Some(Cow::Borrowed(self.joined())),
+            scope => self.msg.scoped(scope),
+        }
+    }
+}
+
+/// A point in time as `HH:MM:SS.mmm` UTC.
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        (secs / 3600) % 24,
+        (secs / 60) % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// A receiving ZMQ socket of one particular type. zeromq has no XSUB socket; for receiving, XSUB
+/// only differs from SUB in how subscriptions are sent upstream, so XSUB probes use a SUB socket.
+pub trait Input: Socket + SocketRecv + 'static {
+    /// Setup after the socket is connected or bound. `topics` are only used by socket types that
+    /// subscribe.
+    fn prepare(&mut self, _topics: &[String]) -> impl Future<Output = ZmqResult<()>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// Everything the inputs report back.
+#[derive(Clone, Debug)]
+pub enum InputEvent {
+    Message(Message),
+    /// The peers currently connected to an endpoint's socket changed.
+    Peers {
+        endpoint: String,
+        peers: Vec<String>,
+    },
+    /// Where an endpoint's bound socket ended up, e.g. with the port the OS picked for port 0.
+    Bound {
+        endpoint: String,
+        local: String,
+    },
+    /// An endpoint's socket changed its connection state.
+    State {
+        endpoint: String,
+        state: ConnectionState,
+    },
+}
+
+/// Where an endpoint's socket is in its connect/retry cycle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// Failed in a way retrying won't fix, e.g. an unparseable address.
+    Error(String),
+    /// Failed and will try again after `delay`.
+    Retrying {
+        error: String,
+        delay: Duration,
+    },
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Error(error) => write!(f, "error: {error}"),
+            ConnectionState::Retrying { error, delay } => {
+                write!(f, "retrying in {}s: {error}", delay.as_secs())
+            }
+        }
+    }
+}
 
 /// A small event handler that wraps input and tick events. Each event
 /// type is handled in its own task and returned to a common `Receiver`
 pub struct Inputs {
-    rx: mpsc::Receiver<Message>,
+    rx: mpsc::Receiver<InputEvent>,
+    recorder: Option<Recorder>,
+    /// The running sockets, or `None` when replaying a capture.
+    sockets: Option<Sockets>,
+}
+
+struct Sockets {
+    tx: mpsc::Sender<InputEvent>,
+    running: HashMap<String, RunningSocket>,
+}
+
+/// One endpoint's input task and the settings it was started with.
+struct RunningSocket {
+    settings: (SocketType, Mode, Vec<String>, Option<Duration>),
+    task: JoinHandle<()>,
 }
 
 impl Inputs {
+    /// Opens one connection per distinct endpoint. Probes that share an address also share the
+    /// socket, which subscribes to the union of their topics.
     pub fn with_probes(probes: &[ProbeConfig]) -> Inputs {
         let (tx, rx) = mpsc::channel(10);
+        let mut inputs = Inputs {
+            rx,
+            recorder: None,
+            sockets: Some(Sockets {
+                tx,
+                running: HashMap::new(),
+            }),
+        };
+        inputs.update(probes);
+        inputs
+    }
+
+    /// Brings the running sockets in line with a new set of probes: sockets nobody uses anymore
+    /// are stopped, new endpoints are opened and endpoints whose settings changed are reopened.
+    /// Does nothing for replays.
+    pub fn update(&mut self, probes: &[ProbeConfig]) {
+        let Some(sockets) = &mut self.sockets else {
+            return;
+        };
+        let mut wanted = probes.iter().into_group_map_by(|p| p.address.clone());
 
-        for p in probes {
-            let p = p.clone();
-            let tx = tx.clone();
-            tokio::spawn(async move {
-                let mut z = ZMQInput::from_probe(&p).await;
-                loop {
-                    // this is blocking!!
-                    let msg = z.get().await;
-                    let name = z.name();
-                    if tx.send((name, msg)).await.is_err() {
+        sockets.running.retain(|address, socket| {
+            let keep = wanted
+                .get(address)
+                .is_some_and(|group| settings(group) == socket.settings);
+            if keep {
+                wanted.remove(address);
+            } else {
+                socket.task.abort();
+            }
+            keep
+        });
+
+        for (address, group) in wanted {
+            let settings = settings(&group);
+            let (socket_type, mode, topics, reconnect_after) = settings.clone();
+            let tx = sockets.tx.clone();
+            let task = match socket_type {
+                SocketType::Sub | SocketType::Xsub => {
+                    spawn_input::<SubSocket>(address.clone(), mode, topics, reconnect_after, tx)
+                }
+                SocketType::Pull => {
+                    spawn_input::<PullSocket>(address.clone(), mode, topics, reconnect_after, tx)
+                }
+                SocketType::Rep => {
+                    spawn_input::<RepSocket>(address.clone(), mode, topics, reconnect_after, tx)
+                }
+                SocketType::Router => {
+                    spawn_input::<RouterSocket>(address.clone(), mode, topics, reconnect_after, tx)
+                }
+            };
+            sockets
+                .running
+                .insert(address, RunningSocket { settings, task });
+        }
+    }
+
+    /// Plays back a capture file instead of connecting anywhere. `speed` multiplies the original
+    /// pace; `None` sends everything as fast as it can be processed.
+    pub fn from_capture(path: &Path, speed: Option<f64>) -> io::Result<Inputs> {
+        let mut reader = CaptureReader::open(path)?;
+        let (tx, rx) = mpsc::channel(10);
+
+        tokio::spawn(async move {
+            let mut previous: Option<SystemTime> = None;
+            loop {
+                let msg = match reader.read() {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Failed to read capture file: {e}");
                         break;
                     }
+                };
+                if let (Some(speed), Some(previous)) = (speed, previous) {
+                    let gap = msg.received.duration_since(previous).unwrap_or_default();
+                    tokio::time::sleep(replay_delay(gap, speed)).await;
                 }
-            });
+                previous = Some(msg.received);
+                if tx.send(InputEvent::Message(msg)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Inputs {
+            rx,
+            recorder: None,
+            sockets: None,
+        })
+    }
+
+    /// Appends every message returned from `next` to a capture file from now on.
+    pub fn record_to(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    pub async fn next(&mut self) -> Option<InputEvent> {
+        let event = self.rx.recv().await?;
+        if let (Some(recorder), InputEvent::Message(msg)) = (&mut self.recorder, &event) {
+            if let Err(e) = recorder.write(msg) {
+                error!("Failed to record message, stopping recording: {e}");
+                self.recorder = None;
+            }
         }
-        Inputs { rx }
+        Some(event)
+    }
+}
+
+/// How long to wait for a gap between two recorded messages at a playback speed, up to
+/// `MAX_REPLAY_DELAY`. Speeds so small the wait can't be represented wait the maximum.
+fn replay_delay(gap: Duration, speed: f64) -> Duration {
+    Duration::try_from_secs_f64(gap.as_secs_f64() / speed)
+        .unwrap_or(MAX_REPLAY_DELAY)
+        .min(MAX_REPLAY_DELAY)
+}
+
+/// What a shared socket is opened with. Validation makes sure all probes on an address agree on
+/// socket type and mode.
+fn settings(probes: &[&ProbeConfig]) -> (SocketType, Mode, Vec<String>, Option<Duration>) {
+    let mode = probes[0].mode;
+    (
+        probes[0].socket_type,
+        mode,
+        subscriptions(probes),
+        reconnect_after(probes, mode),
+    )
+}
+
+/// How long a connected socket waits for traffic before checking for a restarted peer: the
+/// shortest `max_silence` of the probes on it. Bound sockets don't need to, peers reconnect to
+/// them on their own.
+fn reconnect_after(probes: &[&ProbeConfig], mode: Mode) -> Option<Duration> {
+    if mode == Mode::Bind {
+        return None;
     }
+    probes
+        .iter()
+        .filter_map(|p| p.max_silence)
+        .min()
+        .map(|secs| Duration::from_secs(secs.max(1)))
+}
+
+/// The topics a shared socket has to subscribe to so that every probe on it gets its messages. If
+/// any probe wants everything, the socket subscribes to everything.
+fn subscriptions(probes: &[&ProbeConfig]) -> Vec<String> {
+    if probes.iter().any(|p| p.topics.is_empty()) {
+        return Vec::new();
+    }
+    probes
+        .iter()
+        .flat_map(|p| p.topics.iter().cloned())
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub async fn next(&mut self) -> Option<Message> {
-        self.rx.recv().await
+    #[test]
+    fn replay_delays_are_scaled_and_capped() {
+        let second = Duration::from_secs(1);
+        assert_eq!(replay_delay(second, 2.0), Duration::from_millis(500));
+        assert_eq!(replay_delay(second, 1e-300), MAX_REPLAY_DELAY);
+        assert_eq!(
+            replay_delay(Duration::from_secs(86_400), 1.0),
+            MAX_REPLAY_DELAY
+        );
+        assert_eq!(replay_delay(Duration::ZERO, 1e-300), Duration::ZERO);
     }
 }
@@ -1,37 +1,339 @@
-use crate::probe::config::ProbeConfig;
-use zeromq::Socket;
-use zeromq::SocketRecv;
-use zeromq::SubSocket;
-
-pub struct ZMQInput {
-    pub name: String,
-    socket: SubSocket,
+use crate::probe::config::Mode;
+use crate::probe::inputs::{ConnectionState, Input, InputEvent, Message};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_stream::{Stream, StreamExt};
+use zeromq::util::PeerIdentity;
+use zeromq::{
+    PullSocket, RepSocket, RouterSocket, SocketEvent, SubSocket, ZmqError, ZmqMessage, ZmqResult,
+};
+
+/// How long to wait for a peer before reporting a connect as failed. zeromq keeps retrying refused
+/// connections on its own, which would otherwise look like connecting forever.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+impl Input for SubSocket {
+    async fn prepare(&mut self, topics: &[String]) -> ZmqResult<()> {
+        if topics.is_empty() {
+            self.subscribe("").await?;
+        } else {
+            for topic in topics {
+                self.subscribe(topic).await?;
+            }
+        }
+        Ok(())
+    }
 }
 
-impl ZMQInput {
-    pub async fn from_probe(probe: &ProbeConfig) -> ZMQInput {
-        let mut socket = zeromq::SubSocket::new();
-        socket
-            .connect(&probe.address)
-            .await
-            .expect("Failed to connect");
+impl Input for PullSocket {}
 
-        socket.subscribe("").await.expect("Failed to subscribe");
-        ZMQInput {
-            name: probe.name.clone(),
-            socket,
+/// Passive REP monitor: it receives requests but never replies to them, so requesters will wait
+/// for an answer from another peer.
+impl Input for RepSocket {}
+
+/// Passive ROUTER monitor. The first frame of every message is the sending peer's identity.
+impl Input for RouterSocket {}
+
+/// Runs one endpoint's socket until the receiving side goes away. Failures are reported as
+/// connection state and retried with exponential backoff on a fresh socket, so a bad endpoint
+/// never takes the task down with it.
+///
+/// zeromq only retries the first connect and doesn't tell SUB or PULL sockets when a peer goes
+/// away, so a restarted publisher would never be heard from again. With `reconnect_after`, a
+/// socket that receives nothing for that long connects a second socket, and switches to it if that
+/// one connects. The connection stays up in the meantime, so a quiet peer loses nothing.
+pub fn spawn_input<S: Input>(
+    address: String,
+    mode: Mode,
+    topics: Vec<String>,
+    reconnect_after: Option<Duration>,
+    tx: mpsc::Sender<InputEvent>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let report = |state| {
+            tx.send(InputEvent::State {
+                endpoint: address.clone(),
+                state,
+            })
+        };
+        let mut delay = MIN_RETRY_DELAY;
+        let mut local = address.clone();
+        loop {
+            if report(ConnectionState::Connecting).await.is_err() {
+                break;
+            }
+
+            let mut socket = S::new();
+            let mut events = socket.monitor();
+            let error = match open(&mut socket, &local, mode, &topics).await {
+                Ok(endpoint) => {
+                    delay = MIN_RETRY_DELAY;
+                    if mode == Mode::Bind {
+                        // a retry binds the same port again, even if the OS picked it
+                        local = endpoint;
+                        let bound = InputEvent::Bound {
+                            endpoint: address.clone(),
+                            local: local.clone(),
+                        };
+                        if tx.send(bound).await.is_err() {
+                            break;
+                        }
+                    }
+                    if report(ConnectionState::Connected).await.is_err() {
+                        break;
+                    }
+                    let mut peers = HashMap::new();
+                    loop {
+                        match receive(
+                            &mut socket,
+                            &mut events,
+                            &mut peers,
+                            &address,
+                            reconnect_after,
+                            &tx,
+                        )
+                        .await
+                        {
+                            Received::Failed(error) => break error,
+                            Received::Closed => return,
+                            Received::Idle => {
+                                let mut fresh = S::new();
+                                let fresh_events = fresh.monitor();
+                                if open(&mut fresh, &local, mode, &topics).await.is_ok() {
+                                    socket = fresh;
+                                    events = fresh_events;
+                                    peers.clear();
+                                }
+                            }
+                        }
+                    }
+                }
+                Err(error @ ZmqError::Endpoint(_)) => {
+                    let _ = report(ConnectionState::Error(error.to_string())).await;
+                    break;
+                }
+                Err(error) => error,
+            };
+
+            let state = ConnectionState::Retrying {
+                error: error.to_string(),
+                delay,
+            };
+            if report(state).await.is_err() {
+                break;
+            }
+            sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    })
+}
+
+/// Connects or binds the socket. Returns the endpoint it ended up on, which for a bind can differ
+/// from `address`, e.g. with the port the OS picked for port 0.
+async fn open<S: Input>(
+    socket: &mut S,
+    address: &str,
+    mode: Mode,
+    topics: &[String],
+) -> ZmqResult<String> {
+    let endpoint = match mode {
+        Mode::Connect => {
+            timeout(CONNECT_TIMEOUT, socket.connect(address))
+                .await
+                .map_err(|_| ZmqError::Other("no peer listening"))??;
+            address.to_string()
+        }
+        Mode::Bind => socket.bind(address).await?.to_string(),
+    };
+    socket.prepare(topics).await?;
+    Ok(endpoint)
+}
+
+/// Why `receive` stopped.
+enum Received {
+    Failed(ZmqError),
+    /// The receiving side is gone.
+    Closed,
+    /// Nothing arrived for the idle time.
+    Idle,
+}
+
+/// Forwards messages and peer changes until receiving fails, the receiver is gone, or nothing
+/// arrives for `idle`. zeromq only reports disconnects for some socket types, so peers can linger
+/// in the list after they're gone.
+async fn receive<S: Input>(
+    socket: &mut S,
+    events: &mut (impl Stream<Item = SocketEvent> + Unpin),
+    peers: &mut HashMap<PeerIdentity, String>,
+    address: &str,
+    idle: Option<Duration>,
+    tx: &mpsc::Sender<InputEvent>,
+) -> Received {
+    loop {
+        let event = tokio::select! {
+            () = sleep(idle.unwrap_or_default()), if idle.is_some() => return Received::Idle,
+            data = socket.recv() => match data {
+                Ok(data) => InputEvent::Message(to_message(address, data)),
+                Err(error) => return Received::Failed(error),
+            },
+            Some(event) = events.next() => {
+                match event {
+                    SocketEvent::Accepted(peer, id) | SocketEvent::Connected(peer, id) => {
+                        peers.insert(id, peer.to_string());
+                    }
+                    SocketEvent::Disconnected(id) => {
+                        peers.remove(&id);
+                    }
+                    _ => continue,
+                }
+                InputEvent::Peers {
+                    endpoint: address.to_string(),
+                    peers: peers.values().cloned().sorted().collect(),
+                }
+            }
+        };
+        if tx.send(event).await.is_err() {
+            return Received::Closed;
         }
     }
+}
 
-    pub async fn get(&mut self) -> String {
-        let data = self.socket.recv().await.expect("Receive failed");
-        data.iter()
-            .map(|b| String::from_utf8_lossy(b).into_owned())
-            .collect::<Vec<String>>()
-            .join("\n")
+fn to_message(endpoint: &str, data: ZmqMessage) -> Message {
+    Message::new(
+        endpoint,
+        data.into_vec().into_iter().map(|f| f.to_vec()).collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zeromq::{PubSocket, Socket, SocketSend};
+
+    /// The next connection state the input reports, skipping messages and peer changes.
+    async fn next_state(rx: &mut mpsc::Receiver<InputEvent>) -> ConnectionState {
+        loop {
+            let event = timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .expect("state change in time")
+                .expect("input running");
+            if let InputEvent::State { state, .. } = event {
+                return state;
+            }
+        }
+    }
+
+    /// Publishes `text` until the input forwards it. A new subscription takes a moment to reach
+    /// the publisher. The connection state must not change meanwhile.
+    async fn arrives(publisher: &mut PubSocket, rx: &mut mpsc::Receiver<InputEvent>, text: &str) {
+        let received = async {
+            loop {
+                publisher.send(text.into()).await.expect("send");
+                match timeout(Duration::from_millis(100), rx.recv()).await {
+                    Ok(Some(InputEvent::Message(msg))) if msg.text() == text => return,
+                    Ok(Some(InputEvent::State { state, .. })) => panic!("state changed to {state}"),
+                    _ => {}
+                }
+            }
+        };
+        timeout(Duration::from_secs(10), received)
+            .await
+            .expect("message in time");
+    }
+
+    #[tokio::test]
+    async fn idle_connections_switch_to_a_restarted_publisher() {
+        let mut publisher = PubSocket::new();
+        let address = publisher
+            .bind("tcp://127.0.0.1:0")
+            .await
+            .expect("bind")
+            .to_string();
+        let (tx, mut rx) = mpsc::channel(10);
+        let input = spawn_input::<SubSocket>(
+            address.clone(),
+            Mode::Connect,
+            Vec::new(),
+            Some(Duration::from_millis(300)),
+            tx,
+        );
+        assert_eq!(next_state(&mut rx).await, ConnectionState::Connecting);
+        assert_eq!(next_state(&mut rx).await, ConnectionState::Connected);
+        arrives(&mut publisher, &mut rx, "UNIT").await;
+
+        // quiet for longer than the idle time, then the publisher restarts
+        sleep(Duration::from_secs(1)).await;
+        publisher.close().await;
+        let mut publisher = PubSocket::new();
+        publisher.bind(&address).await.expect("bind again");
+
+        arrives(&mut publisher, &mut rx, "STATUS").await;
+        input.abort();
     }
 
-    pub fn name(&self) -> String {
-        self.name.clone()
+    #[tokio::test]
+    async fn topics_only_let_matching_prefixes_through() {
+        let mut publisher = PubSocket::new();
+        let address = publisher
+            .bind("tcp://127.0.0.1:0")
+            .await
+            .expect("bind")
+            .to_string();
+        let (tx, mut rx) = mpsc::channel(10);
+        let input =
+            spawn_input::<SubSocket>(address, Mode::Connect, vec![String::from("UNIT")], None, tx);
+        assert_eq!(next_state(&mut rx).await, ConnectionState::Connecting);
+        assert_eq!(next_state(&mut rx).await, ConnectionState::Connected);
+        arrives(&mut publisher, &mut rx, "UNIT").await;
+
+        for text in ["STATUS", "unit", "UNITS"] {
+            publisher.send(text.into()).await.expect("send");
+        }
+        // skips copies of UNIT that were still on their way
+        let next = async {
+            loop {
+                if let Some(InputEvent::Message(msg)) = rx.recv().await {
+                    if msg.text() != "UNIT" {
+                        return msg.text().to_string();
+                    }
+                }
+            }
+        };
+        let text = timeout(Duration::from_secs(10), next)
+            .await
+            .expect("message in time");
+        assert_eq!(text, "UNITS");
+        input.abort();
+    }
+
+    #[tokio::test]
+    async fn binding_port_0_reports_the_picked_port() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let input = spawn_input::<SubSocket>(
+            String::from("tcp://127.0.0.1:0"),
+            Mode::Bind,
+            Vec::new(),
+            None,
+            tx,
+        );
+        assert_eq!(next_state(&mut rx).await, ConnectionState::Connecting);
+        let Some(InputEvent::Bound { endpoint, local }) = rx.recv().await else {
+            panic!("expected the bound endpoint");
+        };
+        assert_eq!(endpoint, "tcp://127.0.0.1:0");
+        assert!(local.starts_with("tcp://127.0.0.1:"));
+        assert_ne!(local, endpoint);
+        assert_eq!(next_state(&mut rx).await, ConnectionState::Connected);
+
+        let mut publisher = PubSocket::new();
+        publisher.connect(&local).await.expect("connect");
+        arrives(&mut publisher, &mut rx, "UNIT").await;
+        input.abort();
     }
 }
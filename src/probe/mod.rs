@@ -1,6 +1,11 @@
+pub mod alerts;
 pub mod app;
+pub mod check;
 pub mod config;
 pub mod event;
+pub mod headless;
 pub mod inputs;
+pub mod record;
 pub mod state;
+pub mod stats;
 pub mod ui;
@@ -0,0 +1,240 @@
+use crate::probe::inputs::Message;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// Every capture file starts with this, followed by a one byte format version.
+const MAGIC: &[u8; 8] = b"PROBECAP";
+const VERSION: u8 = 1;
+/// How often a recorder flushes what it buffered.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends received messages to a capture file.
+///
+/// Each record is the receive time in microseconds since the epoch, the endpoint and the raw
+/// frames, all length-prefixed little endian, so binary frames survive unchanged.
+pub struct Recorder {
+    out: BufWriter<File>,
+    flushed: Instant,
+}
+
+impl Recorder {
+    /// Opens a capture file for appending, creating it if needed. An existing file has to be a
+    /// capture in the same format, so recording never overwrites or corrupts anything.
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        if !empty {
+            read_header(&mut file)?;
+        }
+        let mut out = BufWriter::new(file);
+        if empty {
+            out.write_all(MAGIC)?;
+            out.write_all(&[VERSION])?;
+            out.flush()?;
+        }
+        Ok(Recorder {
+            out,
+            flushed: Instant::now(),
+        })
+    }
+
+    /// Writes one message. Writes are buffered and flushed at most once per `FLUSH_INTERVAL`, and
+    /// when the recorder is dropped, so a crash loses at most the last moment of traffic.
+    pub fn write(&mut self, msg: &Message) -> io::Result<()> {
+        write_message(&mut self.out, msg)?;
+        if self.flushed.elapsed() >= FLUSH_INTERVAL {
+            self.out.flush()?;
+            self.flushed = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+/// Reads messages back from a capture file written by a [`Recorder`].
+pub struct CaptureReader {
+    input: BufReader<File>,
+}
+
+impl CaptureReader {
+    pub fn open(path: &Path) -> io::Result<CaptureReader> {
+        let mut input = BufReader::new(File::open(path)?);
+        read_header(&mut input)?;
+        Ok(CaptureReader { input })
+    }
+
+    /// The next message, or `None` at the end of the file.
+    pub fn read(&mut self) -> io::Result<Option<Message>> {
+        read_message(&mut self.input)
+    }
+}
+
+fn read_header(r: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a probe capture file",
+        ));
+    }
+    let mut version = [0; 1];
+    r.read_exact(&mut version)?;
+    if version[0] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported capture file version {}", version[0]),
+        ));
+    }
+    Ok(())
+}
+
+fn write_message(w: &mut impl Write, msg: &Message) -> io::Result<()> {
+    let micros = msg
+        .received
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+    w.write_all(&u64::try_from(micros).unwrap_or(u64::MAX).to_le_bytes())?;
+    write_bytes(w, msg.endpoint.as_bytes())?;
+    write_len(w, msg.frames.len())?;
+    for frame in &msg.frames {
+        write_bytes(w, frame)?;
+    }
+    Ok(())
+}
+
+fn read_message(r: &mut impl Read) -> io::Result<Option<Message>> {
+    let mut micros = [0; 8];
+    match r.read_exact(&mut micros) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let endpoint = String::from_utf8(read_bytes(r)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let num_frames = read_len(r)?;
+    let frames = (0..num_frames)
+        .map(|_| read_bytes(r))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(Some(Message {
+        endpoint,
+        received: UNIX_EPOCH + Duration::from_micros(u64::from_le_bytes(micros)),
+        frames,
+    }))
+}
+
+fn write_len(w: &mut impl Write, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large to record"))?;
+    w.write_all(&len.to_le_bytes())
+}
+
+fn read_len(r: &mut impl Read) -> io::Result<usize> {
+    let mut len = [0; 4];
+    r.read_exact(&mut len)?;
+    Ok(u32::from_le_bytes(len) as usize)
+}
+
+fn write_bytes(w: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_len(w, bytes.len())?;
+    w.write_all(bytes)
+}
+
+fn read_bytes(r: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_len(r)?;
+    // a damaged file can claim any length, so only allocate what is actually there
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "capture file ends inside a message",
+        ));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = vec![
+            Message {
+                endpoint: String::from("tcp://127.0.0.1:5556"),
+                received: UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456),
+                frames: vec![b"UNIT".to_vec(), vec![0, 159, 146, 150, 255]],
+            },
+            Message {
+                endpoint: String::from("ipc:///tmp/probe"),
+                received: UNIX_EPOCH + Duration::from_micros(1_700_000_001_000_000),
+                frames: vec![],
+            },
+        ];
+
+        let mut buf = Vec::new();
+        for msg in &messages {
+            write_message(&mut buf, msg).expect("write to Vec");
+        }
+
+        let mut r = Cursor::new(buf);
+        let mut read = Vec::new();
+        while let Some(msg) = read_message(&mut r).expect("valid capture") {
+            read.push(msg);
+        }
+        assert_eq!(read, messages);
+    }
+
+    #[test]
+    fn rejects_truncated_records() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0u64.to_le_bytes());
+        buf.extend_from_slice(&u32::MAX.to_le_bytes());
+        buf.extend_from_slice(b"tcp://");
+        let err = read_message(&mut Cursor::new(buf)).expect_err("truncated record");
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        let mut r = Cursor::new(b"[[probes]]\nname = \"x\"".to_vec());
+        let err = read_header(&mut r).expect_err("not a capture file");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn recording_appends_to_an_existing_capture() {
+        let path = std::env::temp_dir().join(format!("probe-record-{}.cap", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let message = |text: &str| Message::new("tcp://127.0.0.1:5556", vec![text.into()]);
+
+        for text in ["first", "second"] {
+            let mut recorder = Recorder::create(&path).expect("open capture");
+            recorder.write(&message(text)).expect("record");
+        }
+
+        let mut reader = CaptureReader::open(&path).expect("valid capture");
+        let mut frames = Vec::new();
+        while let Some(msg) = reader.read().expect("readable") {
+            frames.push(msg.text().to_string());
+        }
+        assert_eq!(frames, vec!["first", "second"]);
+
+        std::fs::write(&path, "not a capture").expect("overwrite");
+        let err = Recorder::create(&path).err().expect("foreign file");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("untouched"),
+            "not a capture"
+        );
+        std::fs::remove_file(&path).expect("remove capture");
+    }
+}
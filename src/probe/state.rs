@@ -1,9 +1,22 @@
 // use crate::probe::config;
 use regex::Regex;
-use std::cmp;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use super::config::ProbeConfig;
+use super::alerts::Alerts;
+use super::config::{ConfigError, FilterExpr, FilterScope, Mode, ProbeConfig, Probes, SocketType};
+use super::inputs::{ConnectionState, Message, MessageText};
+use super::stats::{Bucket, Histogram, Stats, Summary, Zoom};
+
+/// Messages a probe keeps unless configured otherwise.
+pub const DEFAULT_HISTORY: usize = 60;
+/// Seconds of histogram a probe keeps unless configured otherwise.
+pub const DEFAULT_BUCKETS: usize = 3600;
+/// The chart view never shows less time than this, in seconds.
+const MIN_CHART_SPAN: usize = 10;
 
 #[derive(Debug)]
 pub struct TabsState {
@@ -36,7 +49,14 @@ impl TabsState {
         }
 
         self.num_tabs = new_num_tabs;
+        self.num_probes = num_probes;
         self.probes_per_tab = probes_per_tab;
+
+        // probes can come and go without changing the layout, so keep the selection on one that
+        // still exists.
+        self.selected_probe = self
+            .selected_probe
+            .min(self.probes_on_selected_page().saturating_sub(1));
     }
 
     pub fn next(&mut self) {
@@ -90,81 +110,529 @@ impl TabsState {
 pub struct AppState {
     pub probes: Vec<Probe>,
     pub detail_view: bool,
+    pub detail: DetailState,
+    pub chart_view: bool,
+    pub chart: ChartState,
+    /// How many bytes of messages all probes may keep together, if limited.
+    pub memory_budget: Option<usize>,
+    /// Memory used by the messages the probes keep, counting shared ones once.
+    memory: usize,
+    pub alerts: Alerts,
+}
+
+/// Where the user is in the detail view's message list.
+#[derive(Debug, Default)]
+pub struct DetailState {
+    /// Index into the probe's messages, newest first. 0 follows new messages as they arrive.
+    pub selected: usize,
+    /// Messages per page, as of the last time the list was drawn.
+    pub page_size: usize,
+    pub query: String,
+    /// Whether the search input is open.
+    pub searching: bool,
+}
+
+impl DetailState {
+    /// Whether a message contains the search query. Nothing matches an empty query.
+    pub fn matches(&self, msg: &Message) -> bool {
+        !self.query.is_empty() && msg.text().contains(&self.query)
+    }
+
+    /// Moves the selection by `by` messages, towards older ones if positive, staying in the list.
+    pub fn scroll(&mut self, by: isize, len: usize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(by)
+            .min(len.saturating_sub(1));
+    }
+
+    /// Selects the next message matching the query, towards older ones if `forward`, wrapping
+    /// around at the ends. Returns whether there was one.
+    pub fn find(
+        &mut self,
+        messages: &VecDeque<Arc<SharedMessage>>,
+        forward: bool,
+        from_current: bool,
+    ) -> bool {
+        let len = messages.len();
+        let first = usize::from(!from_current);
+        let found = (first..first + len)
+            .map(|offset| {
+                if forward {
+                    (self.selected + offset) % len
+                } else {
+                    (self.selected + 2 * len - offset) % len
+                }
+            })
+            .find(|&i| self.matches(&messages[i]));
+        if let Some(index) = found {
+            self.selected = index;
+        }
+        found.is_some()
+    }
+}
+
+/// Which part of a probe's timeline the chart view shows.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChartState {
+    /// Seconds shown.
+    pub span: usize,
+    /// Seconds between now and the newest second shown. 0 follows new data.
+    pub offset: usize,
+}
+
+impl Default for ChartState {
+    fn default() -> Self {
+        ChartState {
+            span: 300,
+            offset: 0,
+        }
+    }
+}
+
+impl ChartState {
+    /// Shows half as much time, keeping the newest second shown in place.
+    pub fn zoom_in(&mut self) {
+        self.span = (self.span / 2).max(MIN_CHART_SPAN);
+    }
+
+    /// Shows twice as much time, up to the `retained` seconds there are.
+    pub fn zoom_out(&mut self, retained: usize) {
+        self.span = (self.span * 2).min(retained.max(MIN_CHART_SPAN));
+        self.pan(0, retained);
+    }
+
+    /// Moves `by` seconds towards older data if positive, staying within the `retained` seconds.
+    pub fn pan(&mut self, by: isize, retained: usize) {
+        self.offset = self
+            .offset
+            .saturating_add_signed(by)
+            .min(retained.saturating_sub(self.span));
+    }
+
+    /// A quarter of the span, so panning keeps some of what was shown.
+    pub fn step(&self) -> isize {
+        isize::try_from(self.span / 4).unwrap_or(isize::MAX).max(1)
+    }
+}
+
+/// A message kept by the probes that matched it. Probes on a shared socket keep one copy between
+/// them, which counts once against the memory budget for as long as any of them keeps it.
+#[derive(Debug)]
+pub struct SharedMessage {
+    message: Message,
+    /// How many live probes keep the message. The copies the paused display holds don't count.
+    holders: AtomicUsize,
+}
+
+impl From<Message> for SharedMessage {
+    fn from(message: Message) -> Self {
+        SharedMessage {
+            message,
+            holders: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Deref for SharedMessage {
+    type Target = Message;
+
+    fn deref(&self) -> &Message {
+        &self.message
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Probe {
     pub name: String,
+    pub address: String,
     pub filter: String,
+    /// The compiled filter, or `None` if it matches everything anyway.
+    regex: Option<Regex>,
+    pub scope: FilterScope,
+    expr: Option<FilterExpr>,
+    pub topics: Vec<String>,
+    pub socket_type: SocketType,
+    pub mode: Mode,
+    /// Peers currently connected to this probe's socket.
+    pub peers: Vec<String>,
+    /// Where a bound socket ended up, e.g. with the port the OS picked for port 0.
+    pub bound: Option<String>,
+    /// `None` until the socket reports in, and for replays, which don't have one.
+    pub connection: Option<ConnectionState>,
     pub count: u32,
-    ring: VecDeque<u64>,
-    ring_buffer: u64,
-    messages: VecDeque<String>,
+    histogram: Histogram,
+    messages: VecDeque<Arc<SharedMessage>>,
+    /// How many messages to keep.
+    history: usize,
+    /// How many seconds of histogram to keep.
+    buckets: usize,
+    /// Memory used by `messages`.
+    memory: usize,
+    stats: Stats,
+    /// `stats` as of the last tick.
+    pub summary: Summary,
+    /// Longest time without a matched message before the probe counts as silent.
+    max_silence: Option<Duration>,
+    /// When the probe last matched a message, or was created if it never did.
+    last_message: Instant,
+    /// How long the probe has gone without a message, as of the last tick, if that's longer than
+    /// `max_silence`.
+    pub silent_for: Option<Duration>,
 }
 
 impl AppState {
-    pub fn from_probes(p: &[ProbeConfig]) -> AppState {
-        AppState {
-            probes: p.iter().map(|i| Probe::from(i.clone())).collect(),
+    pub fn from_probes(p: &[ProbeConfig]) -> Result<AppState, ConfigError> {
+        Ok(AppState {
+            probes: p
+                .iter()
+                .map(|i| Probe::try_from(i.clone()))
+                .collect::<Result<_, _>>()?,
             detail_view: false,
+            detail: DetailState::default(),
+            chart_view: false,
+            chart: ChartState::default(),
+            memory_budget: None,
+            memory: 0,
+            alerts: Alerts::default(),
+        })
+    }
+
+    /// State for a whole config, including its global limits and alerts.
+    pub fn from_config(config: &Probes) -> Result<AppState, ConfigError> {
+        Ok(AppState {
+            memory_budget: config.memory_budget(),
+            alerts: Alerts::new(&config.alerts)?,
+            ..AppState::from_probes(&config.probes)?
+        })
+    }
+
+    /// Replaces the probes with the ones in `configs`. Probes that keep their name keep their
+    /// counts, histogram and messages, everything else comes from the new config. Returns how many
+    /// probes were added and removed. If one of the configs can't be turned into a probe, the
+    /// running probes are left alone.
+    pub fn reload(&mut self, configs: &[ProbeConfig]) -> Result<(usize, usize), ConfigError> {
+        let mut probes = configs
+            .iter()
+            .map(|config| Probe::try_from(config.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // sockets that are still running won't report their state again
+        for probe in &mut probes {
+            if let Some(other) = self.probes.iter().find(|p| p.address == probe.address) {
+                probe.share_socket(other);
+            }
+        }
+        let mut previous: HashMap<String, Probe> =
+            self.probes.drain(..).map(|p| (p.name.clone(), p)).collect();
+
+        let mut added = 0;
+        self.probes = probes
+            .into_iter()
+            .map(|mut probe| {
+                match previous.remove(&probe.name) {
+                    Some(old) => self.memory -= probe.keep_history(old),
+                    None => added += 1,
+                }
+                probe
+            })
+            .collect();
+        for probe in previous.values_mut() {
+            self.memory -= probe.drop_messages();
+        }
+        Ok((added, previous.len()))
+    }
+
+    /// Adds a probe. A probe on an address that's already open shares that socket, so it starts
+    /// out with the socket's connection state.
+    pub fn add_probe(&mut self, config: ProbeConfig) -> Result<(), ConfigError> {
+        let mut probe = Probe::try_from(config)?;
+        if let Some(other) = self.probes.iter().find(|p| p.address == probe.address) {
+            probe.share_socket(other);
+        }
+        self.probes.push(probe);
+        Ok(())
+    }
+
+    /// Removes the probe at `index`, along with the messages it kept.
+    pub fn remove_probe(&mut self, index: usize) -> Probe {
+        let mut probe = self.probes.remove(index);
+        self.memory -= probe.drop_messages();
+        probe
+    }
+
+    /// Has the probes at `indices`, which all matched `msg`, keep one shared copy of it, then drops
+    /// the oldest messages if that goes over the memory budget.
+    pub fn keep(&mut self, msg: &Message, indices: &[usize]) {
+        if indices.iter().all(|&i| self.probes[i].history == 0) {
+            return;
+        }
+        let shared = Arc::new(SharedMessage::from(msg.clone()));
+        self.memory += shared.memory();
+        for &i in indices {
+            self.memory -= self.probes[i].keep(&shared);
+        }
+        self.enforce_memory_budget();
+    }
+
+    /// Memory used by the messages of all probes together. A message several probes keep only
+    /// counts once.
+    pub fn memory_usage(&self) -> usize {
+        self.memory
+    }
+
+    /// Drops the oldest messages across all probes until they fit in the memory budget.
+    pub fn enforce_memory_budget(&mut self) {
+        let Some(budget) = self.memory_budget else {
+            return;
+        };
+        while self.memory > budget {
+            let Some(oldest) = self
+                .probes
+                .iter_mut()
+                .filter(|p| !p.messages.is_empty())
+                .min_by_key(|p| p.messages.back().map(|m| m.received))
+            else {
+                break;
+            };
+            self.memory -= oldest.drop_oldest_message();
         }
     }
 
-    pub fn probes_for_tab(&self, index: usize, num: usize) -> Vec<Probe> {
-        let upper = cmp::min(index * num + num, self.probes.len());
-        self.probes[index * num..upper].to_vec()
+    /// The name and socket settings of every probe, enough to open their inputs and check new
+    /// probes against them. Filters are left out.
+    pub fn socket_configs(&self) -> Vec<ProbeConfig> {
+        self.probes
+            .iter()
+            .map(|p| ProbeConfig {
+                name: p.name.clone(),
+                address: p.address.clone(),
+                topics: p.topics.clone(),
+                socket_type: p.socket_type,
+                mode: p.mode,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Checks a probe against the running ones the same way a config file would be checked.
+    pub fn check_new_probe(&self, config: &ProbeConfig) -> Result<(), Vec<ConfigError>> {
+        let mut probes = self.socket_configs();
+        probes.push(config.clone());
+        Probes {
+            probes,
+            ..Default::default()
+        }
+        .validate()
     }
 }
 
 impl Probe {
-    pub fn process_message(&mut self, msg: &str) {
-        if self.filter.is_empty() {
-            self.update_message_buffer(msg);
-        } else {
-            let re = Regex::new(&self.filter).expect("Failed to parse regex");
-            if re.is_match(msg) {
-                self.update_message_buffer(msg);
-                self.count += 1;
-                self.ring_buffer += 1;
-            }
+    /// Counts the message if it passes the filters. Returns whether it did, in which case the
+    /// caller decides whether to keep it. The probes on a socket share `text`, so the frames are
+    /// joined at most once.
+    pub fn process_message(&mut self, msg: &Message, text: &MessageText) -> bool {
+        if !self.matches(msg, text) {
+            return false;
+        }
+        let now = Instant::now();
+        self.stats.record(now, msg.size());
+        self.histogram.record(now, msg.size());
+        self.last_message = now;
+        self.silent_for = None;
+        self.count += 1;
+        true
+    }
+
+    /// Whether the filter and the filter expression both accept the message. A scope that points at
+    /// a frame the message doesn't have never matches.
+    fn matches(&self, msg: &Message, text: &MessageText) -> bool {
+        if self.expr.as_ref().is_some_and(|expr| !expr.eval(text)) {
+            return false;
+        }
+        match &self.regex {
+            // don't build the joined text just to find out it exists
+            None => self.scope == FilterScope::Message || msg.scoped(self.scope).is_some(),
+            Some(re) => text
+                .scoped(self.scope)
+                .is_some_and(|text| re.is_match(&text)),
         }
     }
 
-    pub fn messages(self) -> String {
-        self.messages.clone().make_contiguous().to_vec().join("\n")
+    /// Takes over the state of `other`'s socket, which this probe shares.
+    fn share_socket(&mut self, other: &Probe) {
+        self.connection.clone_from(&other.connection);
+        self.peers.clone_from(&other.peers);
+        self.bound.clone_from(&other.bound);
     }
 
-    pub fn update_message_buffer(&mut self, msg: &str) {
-        self.messages.push_front(msg.to_string());
-        if self.messages.len() >= 60 {
-            self.messages.pop_back();
+    /// Whether a message from this probe's endpoint falls under one of its topic prefixes. The
+    /// socket may be shared with other probes, so it can receive topics this probe didn't ask for.
+    pub fn is_subscribed(&self, msg: &Message) -> bool {
+        self.topics.is_empty()
+            || self
+                .topics
+                .iter()
+                .any(|t| msg.topic().starts_with(t.as_bytes()))
+    }
+
+    /// Replaces the filter, keeping the old one if the new one isn't a valid regex.
+    pub fn set_filter(&mut self, filter: &str) -> Result<(), regex::Error> {
+        self.regex = compile_filter(filter)?;
+        self.filter = filter.to_string();
+        Ok(())
+    }
+
+    /// Takes over the counts, histogram and messages of the probe this one replaces. Returns the
+    /// memory freed by dropping messages beyond this probe's history.
+    fn keep_history(&mut self, old: Probe) -> usize {
+        self.count = old.count;
+        self.histogram = old.histogram;
+        self.histogram.resize(self.buckets);
+        self.messages = old.messages;
+        self.memory = old.memory;
+        self.stats = old.stats;
+        self.summary = old.summary;
+        self.last_message = old.last_message;
+        self.trim_history()
+    }
+
+    pub fn messages(&self) -> &VecDeque<Arc<SharedMessage>> {
+        &self.messages
+    }
+
+    /// Keeps a matched message, dropping the oldest beyond the history. Returns the memory freed,
+    /// see `drop_oldest_message`.
+    fn keep(&mut self, msg: &Arc<SharedMessage>) -> usize {
+        if self.history == 0 {
+            return 0;
+        }
+        msg.holders.fetch_add(1, Ordering::Relaxed);
+        self.memory += msg.memory();
+        self.messages.push_front(Arc::clone(msg));
+        self.trim_history()
+    }
+
+    fn trim_history(&mut self) -> usize {
+        let mut freed = 0;
+        while self.messages.len() > self.history {
+            freed += self.drop_oldest_message();
+        }
+        freed
+    }
+
+    fn drop_messages(&mut self) -> usize {
+        let mut freed = 0;
+        while !self.messages.is_empty() {
+            freed += self.drop_oldest_message();
         }
+        freed
+    }
+
+    /// Drops the oldest message. Returns its memory if no other probe keeps it, 0 otherwise.
+    fn drop_oldest_message(&mut self) -> usize {
+        let Some(msg) = self.messages.pop_back() else {
+            return 0;
+        };
+        self.memory -= msg.memory();
+        if msg.holders.fetch_sub(1, Ordering::Relaxed) == 1 {
+            msg.memory()
+        } else {
+            0
+        }
+    }
+
+    /// Memory used by the kept messages, including ones other probes keep as well.
+    pub fn memory(&self) -> usize {
+        self.memory
     }
 
     // this is called once per tick, so do display related stuff here.
     pub fn update_state(&mut self) {
-        self.ring.push_front(self.ring_buffer);
-        if self.ring.len() >= 180 {
-            self.ring.pop_back();
+        let now = Instant::now();
+        self.histogram.advance(now);
+        self.summary = self.stats.summary(now, &self.histogram);
+        let silence = self.silence();
+        self.silent_for = self
+            .max_silence
+            .filter(|max| silence > *max)
+            .map(|_| silence);
+    }
+
+    /// How long ago the probe last matched a message, or was created if it never did.
+    pub fn silence(&self) -> Duration {
+        self.last_message.elapsed()
+    }
+
+    pub fn topics(&self) -> String {
+        if self.topics.is_empty() {
+            String::from("*")
+        } else {
+            self.topics.join(",")
         }
-        self.ring_buffer = 0;
     }
 
-    pub fn histogram(&self) -> Vec<u64> {
-        self.ring.clone().make_contiguous().to_vec()
+    /// What the probe matched in each kept second, newest first.
+    pub fn timeline(&self) -> &VecDeque<Bucket> {
+        self.histogram.seconds()
+    }
+
+    /// Matched messages per bar at the given zoom level, newest first.
+    pub fn histogram(&self, zoom: Zoom) -> Vec<u64> {
+        self.histogram.bars(zoom)
+    }
+}
+
+/// Compiles a filter, or returns `None` if it matches everything anyway.
+fn compile_filter(filter: &str) -> Result<Option<Regex>, regex::Error> {
+    match filter {
+        "" | ".*" => Ok(None),
+        f => Regex::new(f).map(Some),
     }
 }
 
-impl From<ProbeConfig> for Probe {
-    fn from(item: ProbeConfig) -> Self {
-        Probe {
+impl TryFrom<ProbeConfig> for Probe {
+    type Error = ConfigError;
+
+    fn try_from(item: ProbeConfig) -> Result<Self, ConfigError> {
+        let filter = item.filter.unwrap_or(".*".to_string());
+        let buckets = item.buckets.unwrap_or(DEFAULT_BUCKETS);
+        let regex = compile_filter(&filter).map_err(|error| ConfigError::InvalidRegex {
+            probe: item.name.clone(),
+            error,
+        })?;
+        let expr = item
+            .expr
+            .map(|e| FilterExpr::parse(&e))
+            .transpose()
+            .map_err(|error| ConfigError::InvalidExpression {
+                probe: item.name.clone(),
+                error,
+            })?;
+        Ok(Probe {
             name: item.name,
-            filter: item.filter.unwrap_or(".*".to_string()),
+            address: item.address,
+            filter,
+            regex,
+            scope: item.scope,
+            expr,
+            topics: item.topics,
+            socket_type: item.socket_type,
+            mode: item.mode,
+            peers: Vec::new(),
+            bound: None,
+            connection: None,
             count: 0,
-            ring_buffer: 0,
-            messages: VecDeque::with_capacity(60),
-            ring: VecDeque::with_capacity(60),
-        }
+            messages: VecDeque::new(),
+            history: item.history.unwrap_or(DEFAULT_HISTORY),
+            buckets,
+            histogram: Histogram::new(buckets, Instant::now()),
+            memory: 0,
+            stats: Stats::default(),
+            summary: Summary::default(),
+            max_silence: item.max_silence.map(Duration::from_secs),
+            last_message: Instant::now(),
+            silent_for: None,
+        })
     }
 }
 
@@ -292,4 +760,349 @@ mod tests {
         state.next();
         assert_eq!(state.probes_on_selected_page(), 2);
     }
+
+    #[test]
+    fn selection_stays_on_an_existing_probe() {
+        let mut state = TabsState::new(3);
+        state.recalculate_layout(3, 4);
+        state.next_probe();
+        state.next_probe();
+        assert_eq!(state.selected_probe, 2);
+
+        state.recalculate_layout(2, 4);
+        assert_eq!(state.num_probes, 2);
+        assert_eq!(state.selected_probe, 1);
+    }
+
+    #[test]
+    fn invalid_filter_keeps_the_old_one() {
+        let mut probe = probe_with("UNIT", FilterScope::Message);
+        assert!(probe.set_filter("(").is_err());
+        assert_eq!(probe.filter, "UNIT");
+        assert!(process(&mut probe, &unit_message()));
+
+        probe.set_filter("STATUS").expect("valid regex");
+        assert_eq!(probe.filter, "STATUS");
+        assert!(!process(&mut probe, &unit_message()));
+    }
+
+    #[test]
+    fn reload_keeps_history_by_name() {
+        let config = |name: &str, filter: &str| ProbeConfig {
+            name: name.to_string(),
+            filter: Some(filter.to_string()),
+            address: String::from("tcp://127.0.0.1:5556"),
+            ..Default::default()
+        };
+        let mut state = AppState::from_probes(&[config("a", "UNIT"), config("b", "UNIT")])
+            .expect("valid probes");
+        let msg = unit_message();
+        for probe in &mut state.probes {
+            probe.connection = Some(ConnectionState::Connected);
+            probe.process_message(&msg, &MessageText::new(&msg));
+        }
+        state.keep(&msg, &[0, 1]);
+
+        let (added, removed) = state
+            .reload(&[config("c", ".*"), config("a", "STATUS")])
+            .expect("valid probes");
+        assert_eq!((added, removed), (1, 1));
+
+        let names: Vec<&str> = state.probes.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["c", "a"]);
+        assert_eq!(state.probes[0].count, 0);
+        assert_eq!(state.probes[1].count, 1);
+        assert_eq!(state.probes[1].filter, "STATUS");
+        // b's copy of the message went, a still keeps it
+        assert_eq!(state.memory_usage(), msg.memory());
+        assert!(!process(&mut state.probes[1], &unit_message()));
+        assert_eq!(state.probes[0].connection, Some(ConnectionState::Connected));
+    }
+
+    #[test]
+    fn broken_reload_keeps_running_probes() {
+        let mut state =
+            AppState::from_probes(&[config("a", "tcp://127.0.0.1:5556")]).expect("valid probes");
+        let broken = ProbeConfig {
+            filter: Some(String::from("(")),
+            ..config("b", "tcp://127.0.0.1:5556")
+        };
+
+        let error = state.reload(&[broken]).expect_err("invalid filter");
+        assert!(matches!(error, ConfigError::InvalidRegex { probe, .. } if probe == "b"));
+        assert_eq!(state.probes.len(), 1);
+        assert_eq!(state.probes[0].name, "a");
+    }
+
+    #[test]
+    fn new_probes_are_checked_against_running_ones() {
+        let state =
+            AppState::from_probes(&[config("a", "tcp://127.0.0.1:5556")]).expect("valid probes");
+
+        assert!(
+            state
+                .check_new_probe(&config("b", "tcp://127.0.0.1:5556"))
+                .is_ok()
+        );
+        assert!(matches!(
+            state
+                .check_new_probe(&config("a", "tcp://127.0.0.1:5555"))
+                .expect_err("duplicate name")
+                .as_slice(),
+            [ConfigError::DuplicateName(_)]
+        ));
+        let pull = ProbeConfig {
+            socket_type: SocketType::Pull,
+            ..config("b", "tcp://127.0.0.1:5556")
+        };
+        assert!(matches!(
+            state
+                .check_new_probe(&pull)
+                .expect_err("different socket type")
+                .as_slice(),
+            [ConfigError::ConflictingSocket { .. }]
+        ));
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        let messages: VecDeque<Arc<SharedMessage>> = ["a", "UNIT 1", "b", "UNIT 2"]
+            .iter()
+            .map(|text| Arc::new(Message::new("", vec![text.as_bytes().to_vec()]).into()))
+            .collect();
+        let mut detail = DetailState {
+            query: String::from("UNIT"),
+            ..Default::default()
+        };
+
+        assert!(detail.find(&messages, true, true));
+        assert_eq!(detail.selected, 1);
+        assert!(detail.find(&messages, true, true));
+        assert_eq!(detail.selected, 1);
+        assert!(detail.find(&messages, true, false));
+        assert_eq!(detail.selected, 3);
+        assert!(detail.find(&messages, true, false));
+        assert_eq!(detail.selected, 1);
+        assert!(detail.find(&messages, false, false));
+        assert_eq!(detail.selected, 3);
+
+        detail.query = String::from("nothing");
+        assert!(!detail.find(&messages, true, false));
+        assert_eq!(detail.selected, 3);
+        assert!(!detail.find(&VecDeque::new(), true, false));
+    }
+
+    #[test]
+    fn chart_zoom_and_pan_stay_within_history() {
+        let mut chart = ChartState::default();
+        chart.pan(-10, 1000);
+        assert_eq!(chart.offset, 0);
+        chart.pan(chart.step(), 1000);
+        assert_eq!(chart.offset, 75);
+        chart.pan(10_000, 1000);
+        assert_eq!(chart.offset, 700);
+
+        // zooming out past the history shows all of it
+        chart.zoom_out(1000);
+        chart.zoom_out(1000);
+        assert_eq!((chart.span, chart.offset), (1000, 0));
+
+        for _ in 0..10 {
+            chart.zoom_in();
+        }
+        assert_eq!(chart.span, MIN_CHART_SPAN);
+    }
+
+    #[test]
+    fn history_and_buckets_are_limited() {
+        let mut probe = Probe::try_from(ProbeConfig {
+            history: Some(2),
+            buckets: Some(3),
+            ..Default::default()
+        })
+        .expect("valid probe");
+        for _ in 0..5 {
+            process(&mut probe, &unit_message());
+            probe.update_state();
+        }
+        assert_eq!(probe.messages().len(), 2);
+        assert_eq!(probe.memory(), 2 * unit_message().memory());
+        assert_eq!(probe.histogram(Zoom::Second), vec![5]);
+
+        let mut none = Probe::try_from(ProbeConfig {
+            history: Some(0),
+            ..Default::default()
+        })
+        .expect("valid probe");
+        assert!(process(&mut none, &unit_message()));
+        assert!(none.messages().is_empty());
+        assert_eq!(none.count, 1);
+    }
+
+    #[test]
+    fn memory_budget_drops_the_oldest_messages_first() {
+        let config = |name: &str| ProbeConfig {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut state = AppState::from_probes(&[config("a"), config("b")]).expect("valid probes");
+        let size = unit_message().memory();
+        state.memory_budget = Some(3 * size);
+
+        // a gets the two oldest messages, b the two newest
+        state.keep(&unit_message(), &[0]);
+        state.keep(&unit_message(), &[0]);
+        state.keep(&unit_message(), &[1]);
+        state.keep(&unit_message(), &[1]);
+
+        assert_eq!(state.memory_usage(), 3 * size);
+        assert_eq!(state.probes[0].messages().len(), 1);
+        assert_eq!(state.probes[1].messages().len(), 2);
+    }
+
+    #[test]
+    fn shared_messages_count_once() {
+        let mut state = AppState::from_probes(&[
+            ProbeConfig {
+                history: Some(1),
+                ..config("a", "")
+            },
+            config("b", ""),
+        ])
+        .expect("valid probes");
+        let size = unit_message().memory();
+
+        state.keep(&unit_message(), &[0, 1]);
+        assert_eq!(state.memory_usage(), size);
+        assert!(Arc::ptr_eq(
+            &state.probes[0].messages()[0],
+            &state.probes[1].messages()[0]
+        ));
+
+        // a drops its copy, b still keeps the first message
+        state.keep(&unit_message(), &[0]);
+        assert_eq!(state.memory_usage(), 2 * size);
+        state.remove_probe(1);
+        assert_eq!(state.memory_usage(), size);
+    }
+
+    #[test]
+    fn probes_without_messages_turn_silent() {
+        let mut probe = Probe::try_from(ProbeConfig {
+            max_silence: Some(0),
+            ..Default::default()
+        })
+        .expect("valid probe");
+        std::thread::sleep(Duration::from_millis(5));
+        probe.update_state();
+        assert!(probe.silent_for.is_some());
+
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.silent_for, None);
+
+        let mut quiet = Probe::try_from(ProbeConfig::default()).expect("valid probe");
+        quiet.update_state();
+        assert_eq!(quiet.silent_for, None);
+    }
+
+    /// Runs a message through a probe the way the app does for a single matching probe.
+    fn process(probe: &mut Probe, msg: &Message) -> bool {
+        let matched = probe.process_message(msg, &MessageText::new(msg));
+        if matched {
+            probe.keep(&Arc::new(SharedMessage::from(msg.clone())));
+        }
+        matched
+    }
+
+    fn config(name: &str, address: &str) -> ProbeConfig {
+        ProbeConfig {
+            name: name.to_string(),
+            address: address.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn probe_with(filter: &str, scope: FilterScope) -> Probe {
+        Probe::try_from(ProbeConfig {
+            name: String::from("test"),
+            filter: Some(filter.to_string()),
+            scope,
+            ..Default::default()
+        })
+        .expect("valid probe")
+    }
+
+    fn unit_message() -> Message {
+        Message::new("", vec![b"UNIT".to_vec(), b"10002 45 12".to_vec()])
+    }
+
+    #[test]
+    fn message_scope_matches_any_frame() {
+        let mut probe = probe_with("UNIT", FilterScope::Message);
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 1);
+
+        let mut probe = probe_with("45", FilterScope::Message);
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 1);
+    }
+
+    #[test]
+    fn topic_scope_ignores_body() {
+        let mut probe = probe_with("2", FilterScope::Topic);
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 0);
+
+        let mut probe = probe_with("^UNIT$", FilterScope::Topic);
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 1);
+    }
+
+    #[test]
+    fn frame_scope_ignores_topic() {
+        let mut probe = probe_with("N", FilterScope::Frame(1));
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 0);
+
+        let mut probe = probe_with("2", FilterScope::Frame(1));
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 1);
+    }
+
+    #[test]
+    fn expression_and_filter_must_both_match() {
+        let mut probe = Probe::try_from(ProbeConfig {
+            name: String::from("test"),
+            filter: Some(String::from("45")),
+            expr: Some(String::from(r#"topic is "UNIT" AND NOT "heartbeat""#)),
+            ..Default::default()
+        })
+        .expect("valid probe");
+        process(&mut probe, &unit_message());
+        process(
+            &mut probe,
+            &Message::new("", vec![b"UNIT".to_vec(), b"heartbeat 45".to_vec()]),
+        );
+        process(
+            &mut probe,
+            &Message::new("", vec![b"UNIT".to_vec(), b"12".to_vec()]),
+        );
+        assert_eq!(probe.count, 1);
+    }
+
+    #[test]
+    fn empty_filter_counts_every_message() {
+        let mut probe = probe_with("", FilterScope::Message);
+        process(&mut probe, &unit_message());
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 2);
+        assert_eq!(probe.messages().len(), 2);
+    }
+
+    #[test]
+    fn frame_scope_out_of_range_never_matches() {
+        let mut probe = probe_with(".*", FilterScope::Frame(5));
+        process(&mut probe, &unit_message());
+        assert_eq!(probe.count, 0);
+    }
 }
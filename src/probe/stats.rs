@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Sizes and inter-arrival times cover this much time.
+const WINDOW: Duration = Duration::from_secs(60);
+/// Fast streams could fill the window with millions of messages, so only the newest are kept.
+/// Rates don't depend on this, they come from the histogram.
+const MAX_SAMPLES: usize = 10_000;
+
+/// Rolling size and inter-arrival time statistics over the messages a probe matched.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// Arrival time and size of the messages in the window, newest first.
+    samples: VecDeque<(Instant, usize)>,
+}
+
+/// A point in time view of `Stats`, for display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Messages in the last completed second.
+    pub rate: usize,
+    /// Highest `rate` seen so far.
+    pub peak_rate: usize,
+    /// Bytes in the last completed second.
+    pub throughput: usize,
+    /// Smallest, average and largest message size in bytes, over at most `MAX_SAMPLES` messages.
+    pub size: Option<(usize, usize, usize)>,
+    /// 50th, 95th and 99th percentile of the time between two messages, over at most
+    /// `MAX_SAMPLES` messages.
+    pub gaps: Option<(Duration, Duration, Duration)>,
+}
+
+impl Stats {
+    pub fn record(&mut self, at: Instant, size: usize) {
+        self.samples.push_front((at, size));
+        self.samples.truncate(MAX_SAMPLES);
+        self.expire(at);
+    }
+
+    /// The sampled sizes and gaps as of `now`, along with the rates from `histogram`, which has to
+    /// be advanced to `now`.
+    pub fn summary(&mut self, now: Instant, histogram: &Histogram) -> Summary {
+        self.expire(now);
+        let sizes = self.samples.iter().map(|(_, size)| *size);
+        let size = sizes
+            .clone()
+            .min()
+            .zip(sizes.clone().max())
+            .map(|(min, max)| (min, sizes.sum::<usize>() / self.samples.len(), max));
+
+        let mut gaps: Vec<Duration> = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|((newer, _), (older, _))| newer.saturating_duration_since(*older))
+            .collect();
+        gaps.sort_unstable();
+        let gaps = (!gaps.is_empty()).then(|| {
+            (
+                percentile(&gaps, 50),
+                percentile(&gaps, 95),
+                percentile(&gaps, 99),
+            )
+        });
+
+        Summary {
+            rate: usize::try_from(histogram.last.count).unwrap_or(usize::MAX),
+            peak_rate: usize::try_from(histogram.peak).unwrap_or(usize::MAX),
+            throughput: histogram.last.bytes,
+            size,
+            gaps,
+        }
+    }
+
+    /// Drops samples that fell out of the window.
+    fn expire(&mut self, now: Instant) {
+        while self
+            .samples
+            .back()
+            .is_some_and(|(at, _)| now.saturating_duration_since(*at) > WINDOW)
+        {
+            self.samples.pop_back();
+        }
+    }
+}
+
+/// Width of a histogram bucket.
+const BUCKET: Duration = Duration::from_secs(1);
+
+/// How many one second buckets a histogram bar sums up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Zoom {
+    #[default]
+    Second,
+    TenSeconds,
+    Minute,
+}
+
+impl Zoom {
+    pub fn seconds(self) -> usize {
+        match self {
+            Zoom::Second => 1,
+            Zoom::TenSeconds => 10,
+            Zoom::Minute => 60,
+        }
+    }
+
+    /// The next wider zoom level, back to the narrowest after the widest.
+    #[must_use]
+    pub fn next(self) -> Zoom {
+        match self {
+            Zoom::Second => Zoom::TenSeconds,
+            Zoom::TenSeconds => Zoom::Minute,
+            Zoom::Minute => Zoom::Second,
+        }
+    }
+}
+
+/// Matched messages per second of wall clock time, so what a bar means doesn't depend on how
+/// often the display is redrawn.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    /// Newest first. The newest one is still filling up.
+    buckets: VecDeque<Bucket>,
+    /// When the newest bucket started.
+    start: Instant,
+    /// Buckets started since the histogram was created, so zoomed bars can start at fixed
+    /// boundaries instead of shifting every second.
+    started: usize,
+    /// How many buckets to keep.
+    len: usize,
+    /// The last completed second, kept apart from `buckets` in case only one is kept.
+    last: Bucket,
+    /// The highest count of any completed second.
+    peak: u64,
+}
+
+/// What a probe matched in one second.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bucket {
+    pub count: u64,
+    pub bytes: usize,
+}
+
+impl Histogram {
+    pub fn new(len: usize, now: Instant) -> Histogram {
+        Histogram {
+            buckets: VecDeque::from([Bucket::default()]),
+            start: now,
+            started: 0,
+            len: len.max(1),
+            last: Bucket::default(),
+            peak: 0,
+        }
+    }
+
+    pub fn record(&mut self, at: Instant, size: usize) {
+        self.advance(at);
+        self.buckets[0].count += 1;
+        self.buckets[0].bytes += size;
+    }
+
+    /// Starts an empty bucket for every second that passed since the newest one started.
+    pub fn advance(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.start);
+        let passed = usize::try_from(elapsed.as_secs()).unwrap_or(usize::MAX);
+        if passed == 0 {
+            return;
+        }
+        // only the newest bucket completed in the last second, the others were empty
+        self.last = if passed == 1 {
+            self.buckets[0]
+        } else {
+            Bucket::default()
+        };
+        self.peak = self.peak.max(self.buckets[0].count);
+        for _ in 0..passed.min(self.len) {
+            self.buckets.push_front(Bucket::default());
+        }
+        self.buckets.truncate(self.len);
+        self.start += BUCKET * u32::try_from(passed).unwrap_or(u32::MAX);
+        self.started = self.started.wrapping_add(passed);
+    }
+
+    /// Keeps only the newest `len` buckets from now on.
+    pub fn resize(&mut self, len: usize) {
+        self.len = len.max(1);
+        self.buckets.truncate(self.len);
+    }
+
+    /// Every kept second, newest first.
+    pub fn seconds(&self) -> &VecDeque<Bucket> {
+        &self.buckets
+    }
+
+    /// Message counts summed over `zoom` buckets each, newest first. The newest bar only covers
+    /// the part of its span that has passed.
+    pub fn bars(&self, zoom: Zoom) -> Vec<u64> {
+        let width = zoom.seconds();
+        let newest = self.started % width + 1;
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.count).collect();
+        let (first, rest) = counts.split_at(newest.min(counts.len()));
+        let mut bars = vec![first.iter().sum()];
+        bars.extend(rest.chunks(width).map(|chunk| chunk.iter().sum::<u64>()));
+        bars
+    }
+}
+
+/// Nearest-rank percentile of sorted, non-empty values.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (sorted.len() * p).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let now = Instant::now();
+        let mut stats = Stats::default();
+        assert_eq!(
+            stats.summary(now, &Histogram::new(1, now)),
+            Summary::default()
+        );
+    }
+
+    #[test]
+    fn rates_sizes_and_gaps() {
+        let start = Instant::now();
+        let mut stats = Stats::default();
+        let mut histogram = Histogram::new(60, start);
+        // one message every 100ms, sizes 1 to 20
+        for (i, at) in (0..20)
+            .map(|i| start + Duration::from_millis(i * 100))
+            .enumerate()
+        {
+            stats.record(at, i + 1);
+            histogram.record(at, i + 1);
+        }
+
+        // the second that's still filling up doesn't count yet
+        let now = start + Duration::from_millis(1950);
+        histogram.advance(now);
+        let summary = stats.summary(now, &histogram);
+        assert_eq!(summary.rate, 10);
+        assert_eq!(summary.peak_rate, 10);
+        assert_eq!(summary.throughput, (1..=10).sum::<usize>());
+        assert_eq!(summary.size, Some((1, 10, 20)));
+        let gap = Duration::from_millis(100);
+        assert_eq!(summary.gaps, Some((gap, gap, gap)));
+
+        let now = start + Duration::from_millis(2050);
+        histogram.advance(now);
+        let summary = stats.summary(now, &histogram);
+        assert_eq!(summary.throughput, (11..=20).sum::<usize>());
+
+        // the rate drops when messages stop, the peak stays
+        let now = now + Duration::from_secs(5);
+        histogram.advance(now);
+        let summary = stats.summary(now, &histogram);
+        assert_eq!(summary.rate, 0);
+        assert_eq!(summary.throughput, 0);
+        assert_eq!(summary.peak_rate, 10);
+
+        // sizes and gaps drop out after the long window
+        let summary = stats.summary(now + WINDOW, &histogram);
+        assert_eq!(summary.size, None);
+        assert_eq!(summary.gaps, None);
+    }
+
+    #[test]
+    fn rates_are_not_capped_by_the_samples() {
+        let start = Instant::now();
+        let mut stats = Stats::default();
+        let mut histogram = Histogram::new(1, start);
+        for i in 0..3 * MAX_SAMPLES {
+            let at = start + Duration::from_micros(u64::try_from(i).expect("small"));
+            stats.record(at, 1);
+            histogram.record(at, 1);
+        }
+
+        let now = start + Duration::from_secs(1);
+        histogram.advance(now);
+        let summary = stats.summary(now, &histogram);
+        assert_eq!(summary.rate, 3 * MAX_SAMPLES);
+        assert_eq!(summary.peak_rate, 3 * MAX_SAMPLES);
+        assert_eq!(summary.throughput, 3 * MAX_SAMPLES);
+        assert_eq!(summary.size, Some((1, 1, 1)));
+    }
+
+    #[test]
+    fn histogram_buckets_by_time() {
+        let start = Instant::now();
+        let mut histogram = Histogram::new(5, start);
+        let secs = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        // three messages in the first second, none in the second, one in the third
+        for at in [0.1, 0.5, 0.9, 2.5] {
+            histogram.record(secs(at), 10);
+        }
+        assert_eq!(histogram.bars(Zoom::Second), vec![1, 0, 3]);
+        assert_eq!(
+            histogram.seconds()[2],
+            Bucket {
+                count: 3,
+                bytes: 30
+            }
+        );
+
+        // quiet seconds still add buckets, and old ones drop out
+        histogram.advance(secs(6.0));
+        assert_eq!(histogram.bars(Zoom::Second), vec![0, 0, 0, 0, 1]);
+        histogram.advance(secs(60.0));
+        assert_eq!(histogram.bars(Zoom::Second), vec![0; 5]);
+    }
+
+    #[test]
+    fn zoomed_bars_start_at_fixed_boundaries() {
+        let start = Instant::now();
+        let mut histogram = Histogram::new(100, start);
+        for secs in 0..25 {
+            histogram.record(start + Duration::from_secs(secs), 1);
+        }
+        // seconds 20 to 24 so far, then 10 to 19 and 0 to 9
+        assert_eq!(histogram.bars(Zoom::TenSeconds), vec![5, 10, 10]);
+        assert_eq!(histogram.bars(Zoom::Minute), vec![25]);
+
+        histogram.resize(3);
+        assert_eq!(histogram.bars(Zoom::Second), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn gap_percentiles() {
+        let start = Instant::now();
+        let mut stats = Stats::default();
+        let mut at = start;
+        // 98 gaps of 10ms, one of 500ms and one of 900ms
+        for gap in std::iter::repeat_n(10, 98).chain([500, 900]) {
+            stats.record(at, 1);
+            at += Duration::from_millis(gap);
+        }
+        stats.record(at, 1);
+
+        let (p50, p95, p99) = stats
+            .summary(at, &Histogram::new(1, start))
+            .gaps
+            .expect("gaps");
+        assert_eq!(p50, Duration::from_millis(10));
+        assert_eq!(p95, Duration::from_millis(10));
+        assert_eq!(p99, Duration::from_millis(500));
+    }
+}
@@ -1,37 +1,378 @@
-use crate::probe::app::App;
+use crate::probe::app::{App, FilterEditor, Notice, ProbeForm};
+use crate::probe::inputs::{ConnectionState, Message};
 use crate::probe::state::Probe;
+use crate::probe::stats::{Bucket, Zoom};
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
+    symbols::Marker,
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph, Row, Sparkline, Table, Tabs, Wrap},
+    widgets::{
+        Axis, Block, Borders, Chart, Clear, Dataset, GraphType, LegendPosition, List, ListItem,
+        ListState, Paragraph, Row, Sparkline, Table, Tabs, Wrap,
+    },
 };
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Height of the alert log pane, borders included.
+const ALERT_LOG_HEIGHT: u16 = 6;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
-    if app.state.detail_view {
+    if app.state.chart_view {
+        draw_chart(f, app);
+    } else if app.state.detail_view {
         draw_detail(f, app);
     } else {
         draw_list(f, app);
     }
 }
 pub fn draw_detail(f: &mut Frame, app: &mut App) {
-    let text = app.selected_probe().messages();
-    let p = Paragraph::new(text)
+    let chunks = Layout::default()
+        .constraints(
+            [
+                Constraint::Percentage(40),
+                Constraint::Min(0),
+                Constraint::Length(3),
+            ]
+            .as_ref(),
+        )
+        .split(f.area());
+
+    app.state.detail.page_size = chunks[0].height.saturating_sub(2) as usize;
+    let paused = paused_line(app);
+    let probe = &app.displayed_probes()[app.tabs.selected_probe_index()];
+    let messages = probe.messages();
+    let detail = &app.state.detail;
+
+    let items: Vec<ListItem> = messages
+        .iter()
+        .map(|msg| {
+            let style = if detail.matches(msg) {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!(
+                        "{} {:>3}f {:>6}B ",
+                        msg.timestamp(),
+                        msg.frames.len(),
+                        msg.size()
+                    ),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(msg.text().replace('\n', " | "), style),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
         .block(
             Block::default()
-                .title(app.selected_probe().name)
+                .title(probe.name.clone())
+                .title(paused)
+                .title(
+                    Line::from(format!(
+                        "{} messages, {}",
+                        messages.len(),
+                        format_bytes(probe.memory())
+                    ))
+                    .right_aligned(),
+                )
                 .borders(Borders::ALL),
         )
         .style(Style::default().fg(Color::White).bg(Color::Black))
-        .wrap(Wrap { trim: true });
+        .highlight_style(Style::default().bg(Color::Blue));
+    let mut list_state = ListState::default().with_selected(Some(detail.selected));
+    f.render_stateful_widget(list, chunks[0], &mut list_state);
+
+    let text = messages
+        .get(detail.selected)
+        .map(|msg| message_lines(msg, &detail.query))
+        .unwrap_or_default();
+    let p = Paragraph::new(text)
+        .block(Block::default().title("Message").borders(Borders::ALL))
+        .style(Style::default().fg(Color::White).bg(Color::Black))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, chunks[1]);
+
+    let bar = if detail.searching {
+        Paragraph::new(format!("/{}█", detail.query))
+            .block(Block::default().title("Search").borders(Borders::ALL))
+    } else {
+        Paragraph::new(
+            "j/k: up/down; PgUp/PgDn: page up/down; /: search; n/N: next/previous match; \
+             p: pause; enter: back",
+        )
+        .block(
+            Block::default()
+                .title("Keys")
+                .title(notice_line(app.notice.as_ref()).right_aligned())
+                .borders(Borders::ALL),
+        )
+    };
+    f.render_widget(
+        bar.style(Style::default().fg(Color::White).bg(Color::Black)),
+        chunks[2],
+    );
+}
+
+/// Rate and average message size of the selected probe, one point per second of its timeline.
+pub fn draw_chart(f: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .constraints(
+            [
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Length(3),
+            ]
+            .as_ref(),
+        )
+        .split(f.area());
+
+    let probe = &app.displayed_probes()[app.tabs.selected_probe_index()];
+    let chart = &app.state.chart;
+    let seconds: Vec<(f64, Bucket)> = probe
+        .timeline()
+        .iter()
+        .enumerate()
+        .skip(chart.offset)
+        .take(chart.span)
+        .map(|(ago, bucket)| (-(ago as f64), *bucket))
+        .collect();
+    let rate: Vec<(f64, f64)> = seconds
+        .iter()
+        .map(|(x, bucket)| (*x, bucket.count as f64))
+        .collect();
+    let size: Vec<(f64, f64)> = seconds
+        .iter()
+        .filter(|(_, bucket)| bucket.count > 0)
+        .map(|(x, bucket)| (*x, bucket.bytes as f64 / bucket.count as f64))
+        .collect();
+
+    // time runs from the oldest second shown on the left to the newest on the right
+    let from = Duration::from_secs((chart.offset + chart.span) as u64);
+    let to = Duration::from_secs(chart.offset as u64);
+    let x_axis = TimeAxis {
+        bounds: [-from.as_secs_f64(), -to.as_secs_f64()],
+        labels: vec![
+            format!("-{}", format_span(from)),
+            format!("-{}", format_span((from + to) / 2)),
+            if chart.offset == 0 {
+                String::from("now")
+            } else {
+                format!("-{}", format_span(to))
+            },
+        ],
+    };
+
+    let title = Block::default()
+        .title(format!("{} rate", probe.name))
+        .title(paused_line(app))
+        .title(Line::from(format!("{} shown", format_span(from - to))).right_aligned())
+        .borders(Borders::ALL);
+    draw_series(
+        f,
+        chunks[0],
+        title,
+        "msg/s",
+        &rate,
+        &x_axis,
+        Color::Blue,
+        |v| format!("{v:.0}"),
+    );
+    let title = Block::default()
+        .title(format!("{} average message size", probe.name))
+        .borders(Borders::ALL);
+    draw_series(
+        f,
+        chunks[1],
+        title,
+        "bytes",
+        &size,
+        &x_axis,
+        Color::Green,
+        |v| format!("{v:.0}B"),
+    );
+
+    let keys = Paragraph::new(
+        "h/l: older/newer; +/-: zoom in/out; 0: latest; j/k: other probe; p: pause; c/esc: back",
+    )
+    .block(
+        Block::default()
+            .title("Keys")
+            .title(notice_line(app.notice.as_ref()).right_aligned())
+            .borders(Borders::ALL),
+    )
+    .style(Style::default().fg(Color::White).bg(Color::Black));
+    f.render_widget(keys, chunks[2]);
+}
+
+/// The time axis shared by the chart view's charts, in seconds relative to now.
+struct TimeAxis {
+    bounds: [f64; 2],
+    labels: Vec<String>,
+}
+
+/// One chart of a series over time, with markers and legend entries for its smallest and
+/// largest value.
+#[allow(clippy::too_many_arguments)]
+fn draw_series(
+    f: &mut Frame,
+    area: Rect,
+    block: Block,
+    name: &str,
+    data: &[(f64, f64)],
+    x_axis: &TimeAxis,
+    color: Color,
+    format: impl Fn(f64) -> String,
+) {
+    let max = data.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1));
+    let min = data.iter().copied().min_by(|a, b| a.1.total_cmp(&b.1));
+    let mut datasets = vec![
+        Dataset::default()
+            .name(name.to_string())
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(color))
+            .data(data),
+    ];
+    for (label, point, marker_color) in [("max", &max, Color::Red), ("min", &min, Color::Yellow)] {
+        if let Some(point) = point {
+            datasets.push(
+                Dataset::default()
+                    .name(format!("{label} {}", format(point.1)))
+                    .marker(Marker::Dot)
+                    .graph_type(GraphType::Scatter)
+                    .style(Style::default().fg(marker_color))
+                    .data(std::slice::from_ref(point)),
+            );
+        }
+    }
+
+    let top = max.map_or(1.0, |(_, value)| value.max(1.0) * 1.1);
+    let axis_style = Style::default().fg(Color::DarkGray);
+    let chart = Chart::new(datasets)
+        .block(block)
+        .x_axis(
+            Axis::default()
+                .bounds(x_axis.bounds)
+                .labels(x_axis.labels.clone())
+                .style(axis_style),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, top])
+                .labels([String::from("0"), format(top / 2.0), format(top)])
+                .style(axis_style),
+        )
+        .legend_position(Some(LegendPosition::TopLeft))
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)));
+    f.render_widget(chart, area);
+}
+
+/// One header line for the message, followed by one line per frame with its index and size.
+/// Occurrences of `query` are highlighted.
+fn message_lines(msg: &Message, query: &str) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{} ({} frames, {} bytes)",
+            msg.timestamp(),
+            msg.frames.len(),
+            msg.size()
+        ),
+        Style::default().fg(Color::Blue),
+    ))];
+    lines.extend((0..msg.frames.len()).map(|i| {
+        let mut spans = vec![Span::styled(
+            format!("  [{i}] {:>5}B ", msg.frames[i].len()),
+            Style::default().fg(Color::DarkGray),
+        )];
+        spans.extend(highlight(&msg.frame_text(i), query));
+        Line::from(spans)
+    }));
+    lines
+}
+
+/// Tells that the display is frozen and how much it's missing.
+fn paused_line(app: &App) -> Line<'static> {
+    match &app.paused {
+        Some(paused) => Line::from(format!(" PAUSED ({} new) ", paused.new))
+            .style(Style::default().fg(Color::Black).bg(Color::Yellow)),
+        None => Line::default(),
+    }
+}
+
+/// A byte count in the largest unit that keeps it above 1.
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// A short duration for tight columns: milliseconds below ten seconds, seconds above.
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(10) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{}s", duration.as_secs())
+    }
+}
 
-    f.render_widget(p, f.area());
+/// A time span in whole seconds, minutes and hours, leaving out zero parts, e.g. `1m30s`.
+fn format_span(span: Duration) -> String {
+    let secs = span.as_secs();
+    let parts = [(secs / 3600, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")];
+    let text = parts.iter().filter(|(value, _)| *value > 0).fold(
+        String::new(),
+        |mut text, (value, unit)| {
+            let _ = write!(text, "{value}{unit}");
+            text
+        },
+    );
+    if text.is_empty() {
+        String::from("0s")
+    } else {
+        text
+    }
+}
+
+/// Splits `text` into spans with every occurrence of `query` highlighted.
+fn highlight(text: &str, query: &str) -> Vec<Span<'static>> {
+    if query.is_empty() {
+        return vec![Span::raw(text.to_string())];
+    }
+    let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let mut spans = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(query) {
+        spans.push(Span::raw(rest[..start].to_string()));
+        spans.push(Span::styled(query.to_string(), style));
+        rest = &rest[start + query.len()..];
+    }
+    spans.push(Span::raw(rest.to_string()));
+    spans
 }
 
 pub fn draw_list(f: &mut Frame, app: &mut App) {
-    let num_probes = app.state.probes.len();
-    let probes_per_tab = (f.area().height as usize - 3) / 5;
+    let num_probes = app.displayed_probes().len();
+    let log_height = if app.state.alerts.is_empty() {
+        0
+    } else {
+        ALERT_LOG_HEIGHT
+    };
+    let probes_per_tab = (f.area().height.saturating_sub(3 + log_height) as usize / 5).max(1);
     app.tabs.recalculate_layout(num_probes, probes_per_tab);
     let titles: Vec<Line> = (0..app.tabs.num_tabs)
         .map(|t| {
@@ -41,8 +382,22 @@ pub fn draw_list(f: &mut Frame, app: &mut App) {
             ))
         })
         .collect();
+    let memory = match app.state.memory_budget {
+        Some(budget) => format!(
+            "memory: {} / {}",
+            format_bytes(app.state.memory_usage()),
+            format_bytes(budget)
+        ),
+        None => format!("memory: {}", format_bytes(app.state.memory_usage())),
+    };
     let tabs = Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL).title(app.title))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.title)
+                .title(paused_line(app))
+                .title(Line::from(memory).right_aligned()),
+        )
         .highlight_style(Style::default().fg(Color::Blue))
         .select(app.tabs.selected_tab);
 
@@ -51,6 +406,7 @@ pub fn draw_list(f: &mut Frame, app: &mut App) {
             [
                 Constraint::Length(3),
                 Constraint::Min(0),
+                Constraint::Length(log_height),
                 Constraint::Length(3),
             ]
             .as_ref(),
@@ -58,16 +414,120 @@ pub fn draw_list(f: &mut Frame, app: &mut App) {
         .split(f.area());
     f.render_widget(tabs, chunks[0]);
     draw_tab(f, app, chunks[1]);
+    if log_height > 0 {
+        draw_alert_log(f, app, chunks[2]);
+    }
 
-    let help_text = Line::raw(String::from(
-        "j/k: up/down; enter: show/hide details; h/l: prev/next page; q: quit",
-    ));
-    let p = Paragraph::new(help_text)
-        .block(Block::default().title("Keys").borders(Borders::ALL))
+    let p = app
+        .editor
+        .as_ref()
+        .map_or_else(|| keys_line(app.notice.as_ref()), editor_line)
         .style(Style::default().fg(Color::White).bg(Color::Black))
         .wrap(Wrap { trim: true });
 
-    f.render_widget(p, chunks[2]);
+    f.render_widget(p, chunks[3]);
+
+    if let Some(form) = &app.form {
+        draw_form(f, form);
+    }
+}
+
+/// The add probe form, in a popup over the probes.
+fn draw_form(f: &mut Frame, form: &ProbeForm) {
+    let mut lines: Vec<Line> = ProbeForm::LABELS
+        .iter()
+        .zip(&form.fields)
+        .enumerate()
+        .map(|(i, (label, value))| {
+            let (label_style, cursor) = if i == form.focus {
+                (Style::default().fg(Color::Blue), "█")
+            } else {
+                (Style::default().fg(Color::DarkGray), "")
+            };
+            Line::from(vec![
+                Span::styled(format!("{label:>8}: "), label_style),
+                Span::raw(format!("{value}{cursor}")),
+            ])
+        })
+        .collect();
+    lines.push(Line::default());
+    if let Some(error) = &form.error {
+        lines.push(Line::from(error.clone()).style(Style::default().fg(Color::Red)));
+    }
+
+    let area = f.area();
+    let width = area.width.min(80);
+    let height = area.height.min(7);
+    let popup = Rect::new(
+        (area.width - width) / 2,
+        (area.height - height) / 2,
+        width,
+        height,
+    );
+    let p = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title("Add probe")
+                .title_bottom(
+                    Line::from("tab: next field; enter: add; esc: cancel")
+                        .style(Style::default().fg(Color::DarkGray)),
+                )
+                .borders(Borders::ALL),
+        )
+        .style(Style::default().fg(Color::White).bg(Color::Black));
+    f.render_widget(Clear, popup);
+    f.render_widget(p, popup);
+}
+
+fn keys_line(notice: Option<&Notice>) -> Paragraph<'static> {
+    let help_text = Line::raw(String::from(
+        "j/k: up/down; h/l: prev/next page; enter: details; e: edit filter; a/d: add/remove probe; \
+         z: zoom histogram; c: chart; p: pause; r: reload; q: quit",
+    ));
+    Paragraph::new(help_text).block(
+        Block::default()
+            .title("Keys")
+            .title(notice_line(notice).right_aligned())
+            .borders(Borders::ALL),
+    )
+}
+
+/// The filter input line, with the regex error next to it while the filter is invalid.
+fn editor_line(editor: &FilterEditor) -> Paragraph<'static> {
+    let mut spans = vec![Span::raw(editor.input.clone()), Span::raw("█")];
+    let border = match &editor.error {
+        Some(error) => {
+            spans.push(Span::styled(
+                format!("  {error}"),
+                Style::default().fg(Color::Red),
+            ));
+            Color::Red
+        }
+        None => Color::Blue,
+    };
+    Paragraph::new(Line::from(spans)).block(
+        Block::default()
+            .title(format!("Filter for {}", editor.probe))
+            .title(
+                Line::from("enter: apply; tab: apply and save; esc: cancel")
+                    .right_aligned()
+                    .style(Style::default().fg(Color::DarkGray)),
+            )
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border)),
+    )
+}
+
+fn notice_line(notice: Option<&Notice>) -> Line<'static> {
+    match notice {
+        None => Line::default(),
+        Some(Notice::Info(text)) => {
+            Line::from(text.clone()).style(Style::default().fg(Color::Green))
+        }
+        Some(Notice::Error(text)) => {
+            Line::from(text.clone()).style(Style::default().fg(Color::Red))
+        }
+    }
 }
 
 fn draw_tab(f: &mut Frame, app: &App, area: Rect) {
@@ -79,46 +539,171 @@ fn draw_tab(f: &mut Frame, app: &App, area: Rect) {
 
     // for each probe, draw it in a chunk
     probes.iter().enumerate().for_each(|(i, p)| {
-        let style = if i == app.tabs.selected_probe {
-            Style::default().fg(Color::Blue)
-        } else {
-            Style::default().fg(Color::White)
+        let alerts = app.state.alerts.active(&p.name);
+        let selected = i == app.tabs.selected_probe;
+        let color = match (p.silent_for.is_some(), !alerts.is_empty()) {
+            (true, _) if selected => Color::LightRed,
+            (true, _) => Color::Red,
+            (false, true) if selected => Color::LightYellow,
+            (false, true) => Color::Yellow,
+            _ if selected => Color::Blue,
+            _ => Color::White,
         };
         let block = Block::default()
             .borders(Borders::ALL)
             .title(p.name.clone())
-            .style(style);
+            .title(silence_line(p))
+            .title(alert_line(&alerts))
+            .title(connection_line(p).right_aligned())
+            .title_bottom(peers_line(p))
+            .style(Style::default().fg(color));
         f.render_widget(block, chunks[i]);
-        draw_probe(f, p, chunks[i]);
+        draw_probe(f, p, app.zoom, chunks[i]);
     });
 }
 
-fn draw_probe(f: &mut Frame, probe: &Probe, area: Rect) {
+/// The socket's connection state, colored so failures stand out.
+fn connection_line(probe: &Probe) -> Line<'static> {
+    let Some(state) = &probe.connection else {
+        return Line::default();
+    };
+    let color = match state {
+        ConnectionState::Connecting => Color::Yellow,
+        ConnectionState::Connected => Color::Green,
+        ConnectionState::Error(_) => Color::Red,
+        ConnectionState::Retrying { .. } => Color::LightRed,
+    };
+    Line::from(state.to_string()).style(Style::default().fg(color))
+}
+
+/// How long the probe has gone without a message, once that's over its limit.
+fn silence_line(probe: &Probe) -> Line<'static> {
+    match probe.silent_for {
+        Some(silence) => Line::from(format!(" silent for {}s ", silence.as_secs()))
+            .style(Style::default().fg(Color::White).bg(Color::Red)),
+        None => Line::default(),
+    }
+}
+
+/// The alerts currently firing on a probe.
+fn alert_line(alerts: &[&str]) -> Line<'static> {
+    if alerts.is_empty() {
+        return Line::default();
+    }
+    Line::from(format!(" ALERT: {} ", alerts.join(", ")))
+        .style(Style::default().fg(Color::Black).bg(Color::Yellow))
+}
+
+/// The most recently fired alerts, newest first.
+fn draw_alert_log(f: &mut Frame, app: &App, area: Rect) {
+    let log = &app.state.alerts.log;
+    let lines: Vec<Line> = if log.is_empty() {
+        vec![Line::from("nothing fired yet").style(Style::default().fg(Color::DarkGray))]
+    } else {
+        log.iter()
+            .take(area.height.saturating_sub(2) as usize)
+            .map(|fired| Line::from(fired.line()))
+            .collect()
+    };
+    let p = Paragraph::new(lines).block(
+        Block::default()
+            .title("Alerts")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow)),
+    );
+    f.render_widget(p, area);
+}
+
+/// The probe's mode and the peers connected to its socket, for the bottom border.
+fn peers_line(probe: &Probe) -> Line<'static> {
+    let peers = if probe.peers.is_empty() {
+        String::from("no peers")
+    } else {
+        probe.peers.join(", ")
+    };
+    let mode = match &probe.bound {
+        Some(local) => format!("{} {local}", probe.mode),
+        None => probe.mode.to_string(),
+    };
+    Line::from(format!("{mode}: {peers}")).style(Style::default().fg(Color::DarkGray))
+}
+
+fn draw_probe(f: &mut Frame, probe: &Probe, zoom: Zoom, area: Rect) {
     // split the area in two: left for the table, right for the histogram
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Length(20), Constraint::Min(62)].as_ref())
+        .constraints([Constraint::Length(84), Constraint::Min(20)].as_ref())
         .margin(1)
         .split(area);
 
     let style = Style::default().fg(Color::White);
+    let stats = &probe.summary;
 
-    let rows = vec![Row::new(vec![probe.filter.clone(), probe.count.to_string()]).style(style)];
+    let rows = vec![
+        Row::new(vec![
+            probe.socket_type.to_string(),
+            probe.filter.clone(),
+            probe.topics(),
+            probe.count.to_string(),
+            format!("{}/{}", stats.rate, stats.peak_rate),
+            format!("{}/s", format_bytes(stats.throughput)),
+            stats.size.map_or_else(
+                || String::from("-"),
+                |(min, avg, max)| format!("{min}/{avg}/{max}"),
+            ),
+            stats.gaps.map_or_else(
+                || String::from("-"),
+                |(p50, p95, p99)| {
+                    format!(
+                        "{}/{}/{}",
+                        format_duration(p50),
+                        format_duration(p95),
+                        format_duration(p99)
+                    )
+                },
+            ),
+        ])
+        .style(style),
+    ];
 
-    let widths = [Constraint::Length(8), Constraint::Length(6)];
+    let widths = [
+        Constraint::Length(6),
+        Constraint::Length(8),
+        Constraint::Length(10),
+        Constraint::Length(7),
+        Constraint::Length(9),
+        Constraint::Length(10),
+        Constraint::Length(14),
+        Constraint::Length(13),
+    ];
     let table = Table::new(rows, widths).header(
-        Row::new(vec!["Match", "Count"])
-            .style(Style::default().fg(Color::White))
-            .bottom_margin(1),
+        Row::new(vec![
+            "Type",
+            "Match",
+            "Topics",
+            "Count",
+            "Rate/Peak",
+            "Bytes",
+            "Size mn/av/mx",
+            "Gap 50/95/99",
+        ])
+        .style(Style::default().fg(Color::White))
+        .bottom_margin(1),
     );
     f.render_widget(table, chunks[0]);
 
     // fill the histogram
-    let data = probe.histogram();
+    // newest bars are on the left, so the axis runs from now back to the oldest bar shown
+    let data = probe.histogram(zoom);
+    let shown = data.len().min(usize::from(chunks[1].width));
+    let bar = Duration::from_secs(zoom.seconds() as u64);
+    let span = bar * u32::try_from(shown).unwrap_or(u32::MAX);
     let sparkline = Sparkline::default()
         .block(
             Block::default()
-                .title("Histogram")
+                .title("now")
+                .title(Line::from(format!("{}/bar", format_span(bar))).centered())
+                .title(Line::from(format!("-{}", format_span(span))).right_aligned())
                 .style(Style::default().fg(Color::White)),
         )
         .style(Style::default().fg(Color::Blue))